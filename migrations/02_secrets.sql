@@ -0,0 +1,7 @@
+-- Create secrets table, for API keys referenced as ${secret:NAME}.
+create table if not exists secrets
+(
+    name text primary key not null,
+    value text not null,
+    updated_at integer not null
+);
@@ -0,0 +1,8 @@
+-- Create procmon_samples table, for procmon history that survives restarts.
+create table if not exists procmon_samples
+(
+    ts integer primary key not null,
+    cpu real not null,
+    ram real not null,
+    swap real not null
+);
@@ -0,0 +1,10 @@
+-- Create audit_log table, for actions users take on the server.
+create table if not exists audit_log
+(
+    id integer primary key not null,
+    ts integer not null,
+    username text not null,
+    action text not null,
+    detail text not null,
+    outcome text not null
+);
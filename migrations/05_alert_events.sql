@@ -0,0 +1,10 @@
+-- Create alert_events table, for procmon alerts firing and resolving.
+create table if not exists alert_events
+(
+    id integer primary key not null,
+    ts integer not null,
+    rule text not null,
+    state text not null,
+    value real not null,
+    message text not null
+);
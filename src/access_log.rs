@@ -0,0 +1,203 @@
+/*
+
+Access log.  One line per request with client IP, user, route, status,
+latency and bytes, written to its own rotating file.
+
+*/
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, MatchedPath},
+    http::{HeaderName, Request, header},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::json;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::Instant;
+use time::{OffsetDateTime, macros::format_description};
+use tracing_appender::non_blocking::NonBlocking;
+
+use crate::auth::users::AuthSession;
+use crate::config::{AccessLogConfig, AccessLogFormat, LogRotationConfig};
+use crate::logging::SizeRotatingWriter;
+
+/// Header carrying the request ID, both on the request and echoed on the response.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+struct AccessLog {
+    writer: NonBlocking,
+    format: AccessLogFormat,
+}
+
+static ACCESS_LOG: OnceLock<AccessLog> = OnceLock::new();
+
+/// The username of the logged in user, handed from the inner
+/// `record_access_user` middleware to the outer `access_log` middleware.
+#[derive(Clone)]
+struct AccessUser(String);
+
+/// Open the access log file.  Should be called once at startup.
+pub fn init_access_log(config: &AccessLogConfig, rotation: &LogRotationConfig) {
+    if !config.enabled {
+        tracing::info!("Access log disabled.");
+        return;
+    }
+
+    let writer = match SizeRotatingWriter::new(&config.dir, &config.prefix, rotation.clone()) {
+        Ok(writer) => writer,
+        Err(e) => {
+            tracing::error!("Failed to open access log in {}: {}", config.dir, e);
+            return;
+        }
+    };
+    let (non_blocking, guard) = tracing_appender::non_blocking(writer);
+    // Keep the background writer thread alive for the life of the program.
+    Box::leak(Box::new(guard));
+
+    let access_log = AccessLog {
+        writer: non_blocking,
+        format: config.format.clone(),
+    };
+    if ACCESS_LOG.set(access_log).is_err() {
+        tracing::warn!("Access log was already initialized");
+    }
+}
+
+/// Get the request ID assigned to this request, or "-".
+pub fn request_id<B>(req: &Request<B>) -> &str {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+}
+
+/// Span for each request, so every log line written while handling it
+/// (including from scripts it spawns) carries the request ID.
+pub fn make_request_span<B>(req: &Request<B>) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        request_id = %request_id(req),
+        method = %req.method(),
+        uri = %req.uri(),
+    )
+}
+
+/// Middleware that writes one access log line per request.
+pub async fn access_log(req: Request<Body>, next: Next) -> Response {
+    let Some(log) = ACCESS_LOG.get() else {
+        return next.run(req).await;
+    };
+
+    let start = Instant::now();
+    let started_at = OffsetDateTime::now_utc();
+
+    let client_ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    let request_id = request_id(&req).to_string();
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+    let version = format!("{:?}", req.version());
+    let referer = header_str(&req, header::REFERER);
+    let user_agent = header_str(&req, header::USER_AGENT);
+
+    let response = next.run(req).await;
+
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let status = response.status().as_u16();
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    });
+    let user = response
+        .extensions()
+        .get::<AccessUser>()
+        .map(|u| u.0.clone());
+
+    let line = match log.format {
+        AccessLogFormat::Combined => {
+            let timestamp = started_at
+                .format(format_description!(
+                    "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] +0000"
+                ))
+                .unwrap_or_default();
+            format!(
+                "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {} {:.3}ms\n",
+                client_ip,
+                user.as_deref().unwrap_or("-"),
+                timestamp,
+                method,
+                uri,
+                version,
+                status,
+                bytes.map_or("-".to_string(), |b| b.to_string()),
+                referer.as_deref().unwrap_or("-"),
+                user_agent.as_deref().unwrap_or("-"),
+                request_id,
+                latency_ms,
+            )
+        }
+        AccessLogFormat::Json => {
+            let timestamp = started_at
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap_or_default();
+            let mut line = json!({
+                "time": timestamp,
+                "request_id": request_id,
+                "client_ip": client_ip,
+                "user": user,
+                "method": method,
+                "uri": uri,
+                "route": route,
+                "version": version,
+                "status": status,
+                "latency_ms": latency_ms,
+                "bytes": bytes,
+                "referer": referer,
+                "user_agent": user_agent,
+            })
+            .to_string();
+            line.push('\n');
+            line
+        }
+    };
+
+    let mut writer = log.writer.clone();
+    if let Err(e) = writer.write_all(line.as_bytes()) {
+        tracing::warn!("Failed to write access log line: {}", e);
+    }
+
+    response
+}
+
+/// Middleware that records the logged in user for the access log.
+/// Must sit inside the auth layer so the `AuthSession` is available.
+pub async fn record_access_user(
+    auth_session: AuthSession,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(req).await;
+    if let Some(user) = auth_session.user {
+        response.extensions_mut().insert(AccessUser(user.username));
+    }
+    response
+}
+
+fn header_str<B>(req: &Request<B>, name: HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
@@ -42,9 +42,7 @@ pub async fn adduser(
 }
 
 pub async fn adduser_from_prompt() -> Result<(), Box<dyn Error>> {
-    let mut is_ok = false;
-
-    while !is_ok {
+    loop {
         let mut username = String::new();
         let mut password = String::new();
         let mut access_level_input = String::new();
@@ -79,12 +77,8 @@ pub async fn adduser_from_prompt() -> Result<(), Box<dyn Error>> {
         io::stdin().read_line(&mut confirmation)?;
 
         if confirmation.trim().eq_ignore_ascii_case("y") {
-            is_ok = true;
-
             // Call function with parsed values
             return adduser(&username, &password, access_level).await;
         }
     }
-
-    Ok(())
 }
@@ -1,19 +1,26 @@
 use axum_login::{
-    AuthManagerLayerBuilder, login_required,
+    AuthManagerLayer, AuthManagerLayerBuilder, login_required,
     tower_sessions::{ExpiredDeletion, Expiry, SessionManagerLayer},
 };
 use axum_messages::MessagesManagerLayer;
 use sqlx::SqlitePool;
 use time::Duration;
 use tokio::signal;
-use tower_sessions::cookie::Key;
+use tower_sessions::{cookie::Key, service::SignedCookie};
 use tower_sessions_sqlx_store::SqliteStore;
 
+use crate::access_log::{
+    REQUEST_ID_HEADER, access_log, init_access_log, make_request_span, record_access_user,
+};
 use crate::config::CertMode;
-use crate::config::{SystemConfig, load_or_create_config};
-use crate::htmlv::load_template_config;
+use crate::config::SystemConfig;
+use crate::htmlv::{init_site_name, load_template_config, page_user};
+use crate::metrics::{init_metrics, track_requests};
+use crate::myapi::api_fixtures::init_fixtures;
 use crate::myapi::routes;
+use crate::procmon::{alerts::init_alerts, history::start_history_sampler, start_live_sampler};
 use crate::{add_user::adduser_from_prompt, certs::load_tls_config};
+use crate::{audit, secrets};
 use axum::{
     BoxError, Router,
     body::Body,
@@ -26,11 +33,11 @@ use axum::{
 use axum_extra::extract::Host;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 
 use crate::auth::{login, private, users::Backend};
 
-use tracing;
 pub struct RustyWebApp {
     db: SqlitePool,
     config: SystemConfig,
@@ -48,9 +55,7 @@ async fn first_time_setup(backend: &Backend) -> Result<(), Box<dyn std::error::E
 }
 
 impl RustyWebApp {
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let config = load_or_create_config("config.yaml");
-
+    pub async fn new(config: SystemConfig) -> Result<Self, Box<dyn std::error::Error>> {
         // Ensure the database file exists before proceeding.
         if !std::path::Path::new("thisbackend.db").exists() {
             std::fs::File::create("thisbackend.db")?;
@@ -58,6 +63,9 @@ impl RustyWebApp {
 
         let db = SqlitePool::connect("thisbackend.db").await?;
         sqlx::migrate!().run(&db).await?;
+        secrets::restrict_db_permissions();
+        secrets::load_secrets(&db).await?;
+        audit::init_audit(db.clone());
 
         Ok(Self { db, config })
     }
@@ -69,6 +77,13 @@ impl RustyWebApp {
         // This uses `tower-sessions` to establish a layer that will provide the session
         // as a request extension.
         load_template_config();
+        init_site_name(&self.config.site_name);
+        init_access_log(&self.config.access_log, &self.config.logging.rotation);
+        init_fixtures(&self.config.api_fixtures);
+        start_live_sampler(self.config.procmon.live_interval_ms);
+        init_alerts(self.config.alerts.clone(), self.db.clone()).await;
+        start_history_sampler(self.config.procmon.clone(), self.db.clone());
+        init_metrics(self.db.clone());
         let session_store = SqliteStore::new(self.db.clone());
         session_store.migrate().await?;
 
@@ -80,11 +95,6 @@ impl RustyWebApp {
                 .continuously_delete_expired(tokio::time::Duration::from_secs(60)),
         );
 
-        // tokio::spawn(async move {
-        //     shutdown_signal().await;
-        //     tracing::warn!("Shutting down.");
-        //     shutdown_handle_clone.shutdown();
-        // });
         let key = Key::generate();
 
         let session_layer = SessionManagerLayer::new(session_store)
@@ -101,45 +111,28 @@ impl RustyWebApp {
         first_time_setup(&backend).await?;
         let auth_layer = AuthManagerLayerBuilder::new(backend, session_layer).build();
 
+        let app = build_router(auth_layer, &shutdown_handle);
+
+        // Stop the server cleanly on Ctrl+C or SIGTERM.
+        let signal_handle = shutdown_handle.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            tracing::warn!("Shutting down.");
+            signal_handle.shutdown();
+        });
+
         match self.config.cert_mode {
             CertMode::SelfSigned | CertMode::Manual => {
                 let tls_config = load_tls_config(&self.config.cert_mode).await;
                 tokio::spawn(redirect_http_to_https(self.config.clone()));
 
-                let app = Router::new()
-                    // Public (login-free) routes
-                    .merge(routes())
-                    // Protected (login-required) routes
-                    .nest(
-                        "/protected",
-                        private::router(Arc::new(shutdown_handle.clone()))
-                            .route_layer(login_required!(Backend, login_url = "/login")),
-                    )
-                    // Auth routes (e.g., login, logout)
-                    .merge(login::router())
-                    // Global middleware (auth manager, session layer, logging)
-                    .layer(MessagesManagerLayer)
-                    .layer(auth_layer)
-                    .layer(axum::middleware::from_fn(restrict_to_local_clients))
-                    .layer(TraceLayer::new_for_http());
-
                 let addr = SocketAddr::from(([0, 0, 0, 0], self.config.https));
                 tracing::info!("HTTPS server listening on {}", addr);
 
-                let server_fut = axum_server::bind_rustls(addr, tls_config)
+                axum_server::bind_rustls(addr, tls_config)
                     .handle(shutdown_handle)
-                    .serve(app.into_make_service_with_connect_info::<SocketAddr>());
-                tokio::select! {
-                    res = server_fut => {
-                        res?;
-                    }
-                }
-
-                // After shutdown, abort deletion task
-                deletion_task.abort();
-                let _ = deletion_task.await; // optionally await abort
-
-                Ok(())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await?;
             }
 
             CertMode::None => {
@@ -147,21 +140,55 @@ impl RustyWebApp {
                 let addr = SocketAddr::from(([0, 0, 0, 0], self.config.http));
                 tracing::info!("HTTP server listening on {}", addr);
 
-                // Build router with middleware layers
-                let app = routes()
-                    .layer(axum::middleware::from_fn(restrict_to_local_clients))
-                    .layer(TraceLayer::new_for_http());
                 axum_server::bind(addr)
                     .handle(shutdown_handle)
                     .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                     .await?;
-
-                Ok(())
             }
         }
+
+        // After shutdown, abort deletion task
+        deletion_task.abort();
+        let _ = deletion_task.await; // optionally await abort
+
+        Ok(())
     }
 }
 
+/// Build the application router.
+///
+/// Every `CertMode` serves this same router, so public routes, login,
+/// the `/protected` routes and the global middleware behave the same
+/// over plain HTTP as they do over HTTPS.
+fn build_router(
+    auth_layer: AuthManagerLayer<Backend, SqliteStore, SignedCookie>,
+    shutdown_handle: &axum_server::Handle,
+) -> Router {
+    Router::new()
+        // Public (login-free) routes
+        .merge(routes())
+        // Protected (login-required) routes
+        .nest(
+            "/protected",
+            private::router(Arc::new(shutdown_handle.clone()))
+                .route_layer(login_required!(Backend, login_url = "/login")),
+        )
+        // Auth routes (e.g., login, logout)
+        .merge(login::router())
+        // Global middleware (auth manager, session layer, logging)
+        .layer(axum::middleware::from_fn(record_access_user))
+        .layer(axum::middleware::from_fn(page_user))
+        .layer(MessagesManagerLayer)
+        .layer(auth_layer)
+        .layer(axum::middleware::from_fn(restrict_to_local_clients))
+        .layer(axum::middleware::from_fn(access_log))
+        .layer(axum::middleware::from_fn(track_requests))
+        // Request IDs: assigned first, attached to the trace span, echoed back.
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span::<Body>))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+}
+
 /// Shutdown handler.
 async fn shutdown_signal() {
     tracing::warn!("Checking for shutdown signal...");
@@ -169,7 +196,6 @@ async fn shutdown_signal() {
         signal::ctrl_c().await.expect("failed to listen for ctrl_c");
     };
 
-    tracing::warn!("Shutting down!");
     #[cfg(unix)]
     let terminate = async {
         use tokio::signal::unix::{SignalKind, signal};
@@ -0,0 +1,49 @@
+/*
+
+Audit log of actions users take on the server, such as signalling a
+process.  Kept in the `audit_log` table of thisbackend.db, and also
+written to the server log.
+
+*/
+use sqlx::SqlitePool;
+use std::sync::OnceLock;
+use time::OffsetDateTime;
+
+static AUDIT_DB: OnceLock<SqlitePool> = OnceLock::new();
+
+/// Set the database the audit log is kept in.  Should be called once at
+/// startup, after migrations.
+pub fn init_audit(db: SqlitePool) {
+    if AUDIT_DB.set(db).is_err() {
+        tracing::warn!("Audit log was already initialized");
+    }
+}
+
+/// Record an action by `username`, e.g. ("signal", "SIGTERM to 1234 (python3)", "ok").
+pub async fn record(username: &str, action: &str, detail: &str, outcome: &str) {
+    tracing::warn!(
+        target: "audit",
+        "{} {}: {} -> {}",
+        username,
+        action,
+        detail,
+        outcome
+    );
+    let Some(db) = AUDIT_DB.get() else {
+        tracing::error!("Audit log is not initialized, action not stored");
+        return;
+    };
+    let result = sqlx::query(
+        "INSERT INTO audit_log (ts, username, action, detail, outcome) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(OffsetDateTime::now_utc().unix_timestamp())
+    .bind(username)
+    .bind(action)
+    .bind(detail)
+    .bind(outcome)
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        tracing::error!("Failed to store audit log entry: {}", e);
+    }
+}
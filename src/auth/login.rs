@@ -1,4 +1,4 @@
-use crate::htmlv::get_tera;
+use crate::htmlv::{get_tera, page_context};
 use axum::{
     Form, Router,
     extract::Query,
@@ -8,7 +8,6 @@ use axum::{
 };
 use axum_messages::{Message, Messages};
 use serde::Deserialize;
-use tera::Context;
 use tracing;
 
 use crate::auth::users::{AuthSession, Credentials};
@@ -19,7 +18,7 @@ fn render_login_template(
     next: Option<String>,
 ) -> Result<String, tera::Error> {
     let tera = get_tera();
-    let mut context = Context::new();
+    let mut context = page_context();
     let messages_as_strs: Vec<String> = messages.into_iter().map(|m| m.message).collect();
     context.insert("messages", &messages_as_strs);
     context.insert("next", &next);
@@ -1,12 +1,14 @@
 use std::sync::Arc;
 
 use crate::{
-    htmlv::get_tera,
+    htmlv::{get_tera, page_context},
+    logging,
     my_api_config::RouteFunction,
     myapi::{
-        add_route_to_router, build_help_page_html, load_routes_from_dir,
+        add_route_to_router, build_help_page_html, load_routes_from_dir, log_download,
         shell_script_run::{get_command_statuses_secure, stop_script},
     },
+    procmon,
 };
 
 use crate::auth::users::AuthSession;
@@ -19,19 +21,23 @@ use axum::{
 use axum_messages::{Message, Messages};
 use serde::Deserialize;
 use serde_json::json;
-use tera::Context;
 
 #[derive(Deserialize)]
 pub struct StopRequest {
     script: String,
 }
 
+#[derive(Deserialize)]
+pub struct LogFilterRequest {
+    filter: String,
+}
+
 fn render_protected_template(
     messages: Vec<Message>,
     username: &str,
 ) -> Result<String, tera::Error> {
     let tera = get_tera();
-    let mut context = Context::new();
+    let mut context = page_context();
 
     let messages_as_strs: Vec<String> = messages.into_iter().map(|m| m.message).collect();
     context.insert("messages", &messages_as_strs);
@@ -42,11 +48,6 @@ fn render_protected_template(
     tera.render("private.html", &context)
 }
 
-#[derive(Clone)]
-pub struct AppState {
-    pub shutdown: Arc<axum_server::Handle>,
-}
-
 /// Build the SECURE routes.
 pub fn router(handle: Arc<axum_server::Handle>) -> Router<()> {
     let route_functions = load_routes_from_dir("./json_routes");
@@ -72,8 +73,38 @@ pub async fn stop_command_handler(
     }
 }
 
+/// Show the log filter currently in effect.
+pub async fn get_log_filter_handler() -> impl IntoResponse {
+    Json(json!({ "filter": logging::current_filter() }))
+}
+
+/// Change the log filter at runtime, e.g. `{"filter": "info,Local_Rust_Web_Server::myapi=debug"}`
+pub async fn set_log_filter_handler(
+    auth_session: AuthSession,
+    Json(payload): Json<LogFilterRequest>,
+) -> impl IntoResponse {
+    match auth_session.user {
+        Some(user) => match logging::set_filter(&payload.filter) {
+            Ok(()) => {
+                tracing::warn!(
+                    "User {} changed the log filter to '{}'",
+                    user.username,
+                    payload.filter
+                );
+                Json(json!({ "status": "ok", "filter": logging::current_filter() })).into_response()
+            }
+            Err(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "status": "error", "message": e })),
+            )
+                .into_response(),
+        },
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
 pub fn make_reload_route(handle: Arc<axum_server::Handle>) -> axum::routing::MethodRouter {
-    let reload_route = {
+    {
         let shutdown_handle = handle.clone();
         post(move |auth_session: AuthSession| {
             let shutdown_handle = shutdown_handle.clone();
@@ -99,8 +130,7 @@ pub fn make_reload_route(handle: Arc<axum_server::Handle>) -> axum::routing::Met
                 }
             }
         })
-    };
-    reload_route
+    }
 }
 
 pub fn build_secure_router_from_route_functions(
@@ -111,18 +141,18 @@ pub fn build_secure_router_from_route_functions(
         .route("/", get(self::get::protected))
         .route("/reload", make_reload_route(handle))
         .route("/command_status", get(get_command_statuses_secure))
-        .route("/kill_script", post(stop_command_handler));
+        .route("/kill_script", post(stop_command_handler))
+        .route(
+            "/log_filter",
+            get(get_log_filter_handler).post(set_log_filter_handler),
+        )
+        .nest("/server_logs", logging::viewer::router())
+        .nest("/procmon", procmon::router())
+        .nest("/log_files", log_download::router(route_functions.clone()));
     let help_text = build_help_page_html(route_functions.clone());
 
     for route_func in route_functions {
-        let meta = match &route_func {
-            RouteFunction::NormalPage { meta, .. }
-            | RouteFunction::HelpPage { meta, .. }
-            | RouteFunction::CommandStatus { meta, .. }
-            | RouteFunction::RunCommand { meta, .. }
-            | RouteFunction::GetLogs { meta, .. }
-            | RouteFunction::ApiCaller { meta, .. } => meta,
-        };
+        let meta = route_func.meta();
 
         // only add auth required endpoints to this router.
         if meta.auth_level >= 1 {
@@ -0,0 +1,91 @@
+/*
+
+The `check` command.  Loads the templates and json_routes the way the
+server does and lists which routes use which template, so a bad
+template reference is caught before starting the server.
+
+*/
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use crate::htmlv::{TemplateRef, configured_templates, load_template_config, resolve_template};
+use crate::my_api_config::RouteFunction;
+use crate::myapi::load_routes_from_dir;
+
+/// One place a template is referenced.
+struct TemplateUse {
+    route: String,
+    function_type: &'static str,
+    template: TemplateRef,
+}
+
+/// `Local_Rust_Web_Server check`: exits with an error if any route
+/// references an unknown template.
+pub fn check_command() -> Result<(), Box<dyn Error>> {
+    load_template_config();
+    let routes = load_routes_from_dir("./json_routes");
+
+    let mut uses = Vec::new();
+    for route in &routes {
+        let meta = route.meta();
+        if let Some(template) = route.template_ref() {
+            uses.push(TemplateUse {
+                route: meta.route.clone(),
+                function_type: route.function_type(),
+                template,
+            });
+        }
+        if let RouteFunction::ApiCaller { endpoints, .. } = route {
+            let mut keys: Vec<&String> = endpoints.keys().collect();
+            keys.sort();
+            for key in keys {
+                if let Some(template) = endpoints[key].template_ref() {
+                    uses.push(TemplateUse {
+                        route: format!("{}?endpoint={}", meta.route, key),
+                        function_type: "call_api",
+                        template,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut by_template: BTreeMap<String, Vec<&TemplateUse>> = configured_templates()
+        .into_iter()
+        .map(|file| (file, Vec::new()))
+        .collect();
+    let mut unknown = Vec::new();
+    for template_use in &uses {
+        match resolve_template(&template_use.template) {
+            Ok(file) => by_template.entry(file).or_default().push(template_use),
+            Err(e) => unknown.push((template_use, e)),
+        }
+    }
+
+    println!("Checked {} route(s) in ./json_routes\n", routes.len());
+    for (file, users) in &by_template {
+        println!("{}", file);
+        if users.is_empty() {
+            println!("    (no routes)");
+        }
+        for template_use in users {
+            println!(
+                "    {} ({}, template {})",
+                template_use.route, template_use.function_type, template_use.template
+            );
+        }
+    }
+
+    if unknown.is_empty() {
+        println!("\nAll template references are valid.");
+        return Ok(());
+    }
+    println!("\nUnknown templates:");
+    for (template_use, e) in &unknown {
+        println!(
+            "    {} ({}): {}",
+            template_use.route, template_use.function_type, e
+        );
+    }
+    Err(format!("{} unknown template reference(s)", unknown.len()).into())
+}
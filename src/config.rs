@@ -2,7 +2,7 @@
 All config related stuff is here.
 
 */
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
     fs::File,
     io::{BufReader, Write},
@@ -17,11 +17,369 @@ pub enum CertMode {
     None,       //none
 }
 
+/// Time based rotation schedule for log files.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationSchedule {
+    #[default]
+    Never, //never, only rotate on size
+    Hourly, //hourly
+    Daily,  //daily, at midnight UTC
+}
+
+/// How rotated log archives are named.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveNaming {
+    #[default]
+    Numbered, //numbered, my_app.1.log is the newest
+    Timestamp, //timestamp, my_app.20250101T000000.log
+}
+
+fn default_max_size_mb() -> u64 {
+    8
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+/// Rotation settings shared by every rotating log file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogRotationConfig {
+    /// max_size_mb- rotate once the log would grow past this size.
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    /// max_files- number of rotated archives to keep; 0 keeps one.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// compress- gzip archives after rotating them.
+    #[serde(default)]
+    pub compress: bool,
+    /// rotate_every- never, hourly or daily.
+    #[serde(default)]
+    pub rotate_every: RotationSchedule,
+    /// naming- numbered or timestamp archive names.
+    #[serde(default)]
+    pub naming: ArchiveNaming,
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_max_size_mb(),
+            max_files: default_max_files(),
+            compress: false,
+            rotate_every: RotationSchedule::default(),
+            naming: ArchiveNaming::default(),
+        }
+    }
+}
+
+fn default_log_dir() -> String {
+    "./logs".to_string()
+}
+
+fn default_log_prefix() -> String {
+    "my_app".to_string()
+}
+
+fn default_log_filter() -> String {
+    "info".to_string()
+}
+
+/// Line format of the server's own log.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text, //text, the [LINE] format
+    Json, //json, one JSON object per line
+}
+
+/// Settings for the server's own log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// dir- directory the server log is written to.
+    #[serde(default = "default_log_dir")]
+    pub dir: String,
+    /// prefix- file name of the server log, without the .log extension.
+    #[serde(default = "default_log_prefix")]
+    pub prefix: String,
+    /// filter- RUST_LOG style directives, e.g. "info,Local_Rust_Web_Server::myapi=debug".
+    /// The RUST_LOG environment variable takes priority when set.
+    #[serde(default = "default_log_filter")]
+    pub filter: String,
+    /// format- text or json.
+    #[serde(default)]
+    pub format: LogFormat,
+    /// rotation- rotation settings, also used by the access log.
+    #[serde(default)]
+    pub rotation: LogRotationConfig,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_log_dir(),
+            prefix: default_log_prefix(),
+            filter: default_log_filter(),
+            format: LogFormat::default(),
+            rotation: LogRotationConfig::default(),
+        }
+    }
+}
+
+/// Line format of the access log.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    #[default]
+    Combined, //combined, Apache/nginx "combined" style
+    Json, //json, one JSON object per line
+}
+
+fn default_access_log_enabled() -> bool {
+    true
+}
+
+fn default_access_log_prefix() -> String {
+    "access".to_string()
+}
+
+/// Settings for the per-request access log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    /// enabled- write an access log at all.  by default, true.
+    #[serde(default = "default_access_log_enabled")]
+    pub enabled: bool,
+    /// format- combined or json.  by default, combined.
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// dir- directory the access log is written to.
+    #[serde(default = "default_log_dir")]
+    pub dir: String,
+    /// prefix- file name of the access log, without the .log extension.
+    #[serde(default = "default_access_log_prefix")]
+    pub prefix: String,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_access_log_enabled(),
+            format: AccessLogFormat::default(),
+            dir: default_log_dir(),
+            prefix: default_access_log_prefix(),
+        }
+    }
+}
+
+/// Whether call_api routes record or replay upstream responses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureMode {
+    #[default]
+    Off, //off, always call the upstream
+    Record, //record, call the upstream and save each response as a fixture
+    Replay, //replay, serve saved fixtures and never call out
+}
+
+fn default_fixtures_dir() -> String {
+    "./fixtures".to_string()
+}
+
+/// Settings for call_api fixtures.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiFixturesConfig {
+    /// mode- off, record or replay.  Routes may override it with `fixture_mode`.
+    #[serde(default)]
+    pub mode: FixtureMode,
+    /// dir- directory fixtures are saved in.
+    #[serde(default = "default_fixtures_dir")]
+    pub dir: String,
+}
+
+impl Default for ApiFixturesConfig {
+    fn default() -> Self {
+        Self {
+            mode: FixtureMode::default(),
+            dir: default_fixtures_dir(),
+        }
+    }
+}
+
+fn default_live_interval_ms() -> u64 {
+    1000
+}
+
+fn default_sample_interval_secs() -> u64 {
+    5
+}
+
+fn default_history_hours() -> u64 {
+    24
+}
+
+fn default_signal_access_level() -> i32 {
+    1
+}
+
+/// Settings for procmon's background sampler and process list.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcmonConfig {
+    /// live_interval_ms- how often usage is refreshed for /procmon and the status bar.
+    #[serde(default = "default_live_interval_ms")]
+    pub live_interval_ms: u64,
+    /// sample_interval_secs- how often usage is sampled into the history.
+    #[serde(default = "default_sample_interval_secs")]
+    pub sample_interval_secs: u64,
+    /// history_hours- how much history is kept.
+    #[serde(default = "default_history_hours")]
+    pub history_hours: u64,
+    /// persist- also keep the history in thisbackend.db, so it survives restarts.
+    #[serde(default)]
+    pub persist: bool,
+    /// signal_access_level- users with an access_level above this may send
+    /// SIGTERM/SIGKILL from the process list.
+    #[serde(default = "default_signal_access_level")]
+    pub signal_access_level: i32,
+}
+
+impl Default for ProcmonConfig {
+    fn default() -> Self {
+        Self {
+            live_interval_ms: default_live_interval_ms(),
+            sample_interval_secs: default_sample_interval_secs(),
+            history_hours: default_history_hours(),
+            persist: false,
+            signal_access_level: default_signal_access_level(),
+        }
+    }
+}
+
+/// What an alert rule watches.  Usage is in percent, temperature in
+/// degrees Celsius, load is the one minute average.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    Cpu,
+    Ram,
+    Swap,
+    Disk,
+    Temperature,
+    Load,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertOp {
+    #[default]
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = "<")]
+    Below,
+}
+
+/// Parse a duration such as "90", "30s", "15m", "1h" or "7d" into seconds.
+pub(crate) fn parse_range(range: &str) -> Option<u64> {
+    let range = range.trim();
+    let (number, unit) = match range.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => range.split_at(split),
+        None => (range, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// A duration such as 120, "90s", "2m" or "1h", in seconds.
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Duration {
+        Secs(u64),
+        Text(String),
+    }
+    match Duration::deserialize(deserializer)? {
+        Duration::Secs(secs) => Ok(secs),
+        Duration::Text(text) => parse_range(&text).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid duration '{}', use e.g. 90s, 2m or 1h",
+                text
+            ))
+        }),
+    }
+}
+
+/// One alert rule, e.g. disk `/` above 90 for 5m.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// name- unique, used in notifications and the history.
+    pub name: String,
+    pub metric: AlertMetric,
+    /// target- for disk, the mount point; for temperature, part of the
+    /// sensor label.  Every disk or sensor is checked when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// op- ">" (default) or "<".
+    #[serde(default)]
+    pub op: AlertOp,
+    pub threshold: f64,
+    /// for- how long the threshold must be crossed before the alert fires.
+    #[serde(rename = "for", default, deserialize_with = "deserialize_duration")]
+    pub for_secs: u64,
+}
+
+/// Where fired and resolved alerts are sent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertNotifier {
+    /// POST the alert as JSON.  The url may use ${secret:NAME}.
+    Webhook { url: String },
+    /// Run with `sh -c`, with the alert in ALERT_* environment variables.
+    Command { command: String },
+}
+
+/// Threshold alerts, checked each time procmon takes a sample.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub notifiers: Vec<AlertNotifier>,
+}
+
+fn default_site_name() -> String {
+    "The Gnomelab".to_string()
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SystemConfig {
     pub http: u16,
     pub https: u16,
     pub cert_mode: CertMode,
+    /// site_name- shown in the nav bar of every page.
+    #[serde(default = "default_site_name")]
+    pub site_name: String,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    #[serde(default)]
+    pub api_fixtures: ApiFixturesConfig,
+    #[serde(default)]
+    pub procmon: ProcmonConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
 }
 
 // Load in or create the YAML if it doesn't exist already.
@@ -31,6 +389,12 @@ pub fn load_or_create_config(path: &str) -> SystemConfig {
             http: 8080,
             https: 8443,
             cert_mode: CertMode::None,
+            site_name: default_site_name(),
+            logging: LoggingConfig::default(),
+            access_log: AccessLogConfig::default(),
+            api_fixtures: ApiFixturesConfig::default(),
+            procmon: ProcmonConfig::default(),
+            alerts: AlertsConfig::default(),
         };
 
         let yaml = serde_yaml::to_string(&default).expect("Failed to serialize default config");
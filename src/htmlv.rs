@@ -1,18 +1,126 @@
-use axum::http::{HeaderValue, header};
+use axum::body::Body;
+use axum::http::{HeaderValue, Request, header};
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use mime::TEXT_HTML_UTF_8;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 use std::sync::OnceLock;
 use tera::{Context, Tera};
-use tracing;
+
+use crate::auth::users::AuthSession;
 
 static TERA: OnceLock<Tera> = OnceLock::new();
-static TEMPLATE_MAP: OnceLock<HashMap<i32, String>> = OnceLock::new();
+static TEMPLATE_MAP: OnceLock<TemplateMap> = OnceLock::new();
+static SITE_NAME: OnceLock<String> = OnceLock::new();
+static NAV: OnceLock<Vec<NavLink>> = OnceLock::new();
+
+tokio::task_local! {
+    /// The logged in user for the request being handled, set by `page_user`.
+    static PAGE_USER: Option<PageUser>;
+}
 
 const TEMPLATE_CONFIG_PATH: &str = "./templates";
 
+/// The template used when a reference can't be resolved.
+pub const DEFAULT_TEMPLATE: &str = "template.html";
+
+/// Templates listed in the template_config.json files, by number and by name.
+#[derive(Debug, Default)]
+struct TemplateMap {
+    by_num: HashMap<i32, String>,
+    by_name: HashMap<String, String>,
+}
+
+/// A route's template: a number or name from template_config.json, or the
+/// name of a file in ./templates, with or without `.html`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TemplateRef {
+    Num(i32),
+    Name(String),
+}
+
+impl fmt::Display for TemplateRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateRef::Num(num) => write!(f, "{}", num),
+            TemplateRef::Name(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+fn has_template(name: &str) -> bool {
+    TERA.get()
+        .is_some_and(|tera| tera.get_template_names().any(|t| t == name))
+}
+
+/// The template file a reference points to.
+pub fn resolve_template(template: &TemplateRef) -> Result<String, String> {
+    let map = TEMPLATE_MAP.get();
+    let file = match template {
+        TemplateRef::Num(num) => map
+            .and_then(|m| m.by_num.get(num))
+            .cloned()
+            .ok_or_else(|| format!("no template {} in template_config.json", num))?,
+        TemplateRef::Name(name) => {
+            if let Some(file) = map.and_then(|m| m.by_name.get(name)) {
+                file.clone()
+            } else {
+                let name = name
+                    .trim_start_matches("./")
+                    .trim_start_matches("templates/");
+                [name.to_string(), format!("{}.html", name)]
+                    .into_iter()
+                    .find(|candidate| has_template(candidate))
+                    .ok_or_else(|| {
+                        format!(
+                            "no template named '{}' in template_config.json or ./templates",
+                            name
+                        )
+                    })?
+            }
+        }
+    };
+    if has_template(&file) {
+        Ok(file)
+    } else {
+        Err(format!(
+            "template {} is {}, which is not in ./templates",
+            template, file
+        ))
+    }
+}
+
+/// Resolve a route's template, reporting an unknown one and using
+/// `template.html` instead.
+pub fn template_or_default(template: &TemplateRef, route: &str) -> String {
+    resolve_template(template).unwrap_or_else(|e| {
+        tracing::error!("Route {}: {}, using {}", route, e, DEFAULT_TEMPLATE);
+        DEFAULT_TEMPLATE.to_string()
+    })
+}
+
+/// Every template named in the template_config.json files.
+pub fn configured_templates() -> Vec<String> {
+    let mut files: Vec<String> = TEMPLATE_MAP
+        .get()
+        .map(|m| {
+            m.by_num
+                .values()
+                .chain(m.by_name.values())
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files.dedup();
+    files
+}
+
 /// Initializes the global Tera engine by loading all templates under ./templates
 /// Should be called once at startup.
 pub fn init_tera() {
@@ -32,11 +140,8 @@ pub fn get_tera() -> &'static Tera {
 }
 
 /// Read the contents of a json file, and
-/// add it into mapped.
-fn parse_and_extend_template_map(
-    file_path: &Path,
-    mapped: &mut HashMap<i32, String>,
-) -> Result<(), String> {
+/// add it into mapped.  Integer keys are template numbers, others names.
+fn parse_and_extend_template_map(file_path: &Path, mapped: &mut TemplateMap) -> Result<(), String> {
     let raw = fs::read_to_string(file_path)
         .map_err(|e| format!("Failed to read {}: {}", file_path.display(), e))?;
 
@@ -46,9 +151,12 @@ fn parse_and_extend_template_map(
     for (k, v) in parsed {
         if let Ok(key) = k.parse::<i32>() {
             tracing::info!("Loaded template: {} => {}", key, v);
-            mapped.insert(key, v);
+            mapped.by_num.insert(key, v);
+        } else if !k.is_empty() {
+            tracing::info!("Loaded template: '{}' => {}", k, v);
+            mapped.by_name.insert(k, v);
         } else {
-            tracing::warn!("Skipping invalid template key '{}'", k);
+            tracing::warn!("Skipping empty template key in {}", file_path.display());
         }
     }
 
@@ -59,9 +167,9 @@ fn parse_and_extend_template_map(
 /// and any additional `template_config.json` files found in the `templates` directory
 /// into the global `TEMPLATE_MAP`.
 ///
-/// This function expects the JSON files to map stringified integers (as keys)
-/// to template file names. Keys are parsed into `i32`, and only valid entries
-/// are included in the final map.
+/// This function expects the JSON files to map stringified integers or
+/// names (as keys) to template file names.  Integer keys are parsed into
+/// `i32`; any other key is a name routes can use as `"template"`.
 ///
 /// The function will panic if any config file is missing or malformed.
 /// It will also panic if `TEMPLATE_MAP` has already been set.
@@ -74,7 +182,7 @@ pub fn load_template_config() {
 
     let template_dir = Path::new(TEMPLATE_CONFIG_PATH);
 
-    let mut mapped = HashMap::new();
+    let mut mapped = TemplateMap::default();
 
     let initial_config_path = template_dir.join("template_config.json");
     parse_and_extend_template_map(&initial_config_path, &mut mapped)
@@ -90,54 +198,125 @@ pub fn load_template_config() {
         if path.is_file()
             && path.extension().unwrap_or_default() == "json"
             && path != initial_config_path
+            && let Err(e) = parse_and_extend_template_map(&path, &mut mapped)
         {
-            if let Err(e) = parse_and_extend_template_map(&path, &mut mapped) {
-                tracing::warn!("{}", e);
-            }
+            tracing::warn!("{}", e);
         }
     }
-    for (key, value) in &mapped {
+    for (key, value) in &mapped.by_num {
         tracing::info!("Template config {}: {}", key, value);
     }
+    for (key, value) in &mapped.by_name {
+        tracing::info!("Template config '{}': {}", key, value);
+    }
     init_tera();
     TEMPLATE_MAP.set(mapped).expect("TEMPLATE_MAP already set");
 }
 
+/// A nav bar link to a route from json_routes.
+#[derive(Serialize, Clone, Debug)]
+pub struct NavLink {
+    pub title: String,
+    pub href: String,
+    /// only shown to logged in users.
+    pub protected: bool,
+}
+
+/// The logged in user, as templates see it.
+#[derive(Serialize, Clone, Debug)]
+pub struct PageUser {
+    pub username: String,
+    pub access_level: i32,
+}
+
+/// Set the site name shown on every page.  Should be called once at startup.
+pub fn init_site_name(name: &str) {
+    if SITE_NAME.set(name.to_string()).is_err() {
+        tracing::warn!("Site name was already set");
+    }
+}
+
+/// Set the nav bar links.  Should be called once, when routes are loaded.
+pub fn init_nav(links: Vec<NavLink>) {
+    tracing::info!("Nav bar: {} link(s)", links.len());
+    if NAV.set(links).is_err() {
+        tracing::warn!("Nav bar was already set");
+    }
+}
+
+pub fn site_name() -> &'static str {
+    SITE_NAME.get().map_or("The Gnomelab", |name| name.as_str())
+}
+
+/// Middleware that makes the logged in user available to every page
+/// rendered while handling the request.  Must sit inside the auth layer.
+pub async fn page_user(auth_session: AuthSession, req: Request<Body>, next: Next) -> Response {
+    let user = auth_session.user.map(|user| PageUser {
+        username: user.username,
+        access_level: user.access_level,
+    });
+    PAGE_USER.scope(user, next.run(req)).await
+}
+
+fn current_user() -> Option<PageUser> {
+    PAGE_USER.try_with(|user| user.clone()).ok().flatten()
+}
+
+/// A context holding what every page gets: `site_name`, the `nav` links
+/// the current user may see, `user` and `username` (empty when logged out).
+pub fn page_context() -> Context {
+    let user = current_user();
+    let nav: Vec<&NavLink> = NAV
+        .get()
+        .map(|links| {
+            links
+                .iter()
+                .filter(|link| !link.protected || user.is_some())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut context = Context::new();
+    context.insert("site_name", site_name());
+    context.insert("nav", &nav);
+    context.insert(
+        "username",
+        user.as_ref().map_or("", |user| user.username.as_str()),
+    );
+    context.insert("user", &user);
+    context
+}
+
 /// Trait for rendering a value into an HTML string using a specified template.
 ///
 /// This trait supports dynamic HTML generation using a shared template system.
-/// Templates are populated by substituting `{{ title }}` and `{{ body }}` markers.
+/// Templates are populated by substituting `{{ title }}` and `{{ body }}` markers,
+/// alongside the shared values from [`page_context`].
+/// `template` is a template file, as given by [`resolve_template`].
 pub trait RenderHtml {
-    fn render_html(self, template_type: i32) -> String;
+    fn render_html(self, template: &str) -> String;
     fn render_html_from_int(self, template_type: i32) -> String
     where
         Self: Sized,
     {
-        self.render_html(template_type)
+        let template = resolve_template(&TemplateRef::Num(template_type))
+            .unwrap_or_else(|_| DEFAULT_TEMPLATE.to_string());
+        self.render_html(&template)
     }
 }
 
 /// Implementations for RenderHTML with different types of strings
 impl RenderHtml for (&str, &str) {
-    fn render_html(self, template_type: i32) -> String {
-        let template_id = template_type;
-
-        let template_name = TEMPLATE_MAP
-            .get()
-            .and_then(|m| m.get(&template_id))
-            .cloned()
-            .unwrap_or_else(|| "template.html".to_string());
-
+    fn render_html(self, template_name: &str) -> String {
         // get tera instance
         let tera = get_tera();
 
         // initalize context
-        let mut context = Context::new();
+        let mut context = page_context();
         context.insert("title", self.0);
         context.insert("body", self.1);
 
-        context.insert("username", "");
-        match tera.render(&template_name, &context) {
+        match tera.render(template_name, &context) {
             Ok(html) => html,
             Err(err) => {
                 tracing::error!("Template rendering failed for {}: {}", template_name, err);
@@ -147,20 +326,12 @@ impl RenderHtml for (&str, &str) {
     }
 }
 impl RenderHtml for (&str, &str, &str) {
-    fn render_html(self, template_type: i32) -> String {
-        let template_id = template_type;
-
-        let template_name = TEMPLATE_MAP
-            .get()
-            .and_then(|m| m.get(&template_id))
-            .cloned()
-            .unwrap_or_else(|| "template.html".to_string());
-
+    fn render_html(self, template_name: &str) -> String {
         // get tera instance
         let tera = get_tera();
 
         // initalize context
-        let mut context = Context::new();
+        let mut context = page_context();
         context.insert("title", self.0);
         context.insert("username", self.2);
 
@@ -176,7 +347,7 @@ impl RenderHtml for (&str, &str, &str) {
 
         context.insert("body", &rendered_body);
 
-        match tera.render(&template_name, &context) {
+        match tera.render(template_name, &context) {
             Ok(html) => html,
             Err(err) => {
                 tracing::error!("Template rendering failed for {}: {}", template_name, err);
@@ -187,31 +358,64 @@ impl RenderHtml for (&str, &str, &str) {
 }
 
 impl RenderHtml for (String, &str) {
-    fn render_html(self, template_type: i32) -> String {
-        (self.0.as_str(), self.1).render_html(template_type)
+    fn render_html(self, template: &str) -> String {
+        (self.0.as_str(), self.1).render_html(template)
     }
 }
 impl RenderHtml for (String, String) {
-    fn render_html(self, template_type: i32) -> String {
-        (self.0.as_str(), self.1.as_str()).render_html(template_type)
+    fn render_html(self, template: &str) -> String {
+        (self.0.as_str(), self.1.as_str()).render_html(template)
     }
 }
 impl RenderHtml for (String, String, String) {
-    fn render_html(self, template_type: i32) -> String {
-        (self.0.as_str(), self.1.as_str(), self.2.as_str()).render_html(template_type)
+    fn render_html(self, template: &str) -> String {
+        (self.0.as_str(), self.1.as_str(), self.2.as_str()).render_html(template)
     }
 }
 
 impl RenderHtml for (&str, String) {
-    fn render_html(self, template_type: i32) -> String {
-        (self.0, self.1.as_str()).render_html(template_type)
+    fn render_html(self, template: &str) -> String {
+        (self.0, self.1.as_str()).render_html(template)
     }
 }
 
 impl RenderHtml for &str {
-    fn render_html(self, template_type: i32) -> String {
-        ("The Gnomelab", self).render_html(template_type)
+    fn render_html(self, template: &str) -> String {
+        (site_name(), self).render_html(template)
+    }
+}
+
+/// Render JSON data through a template, for call_api routes.
+///
+/// The data is in the context as `data`, and an object's fields are also
+/// top-level variables, except where they clash with the page context
+/// (`nav`, `user`, `username`, `site_name`) or `title`, `body` and `data`.  `body` holds the data as escaped JSON, so the
+/// standard page templates still show something useful.
+pub fn render_data_html(
+    template_name: &str,
+    title: &str,
+    data: &serde_json::Value,
+) -> Result<String, String> {
+    let mut context = Context::new();
+    if let serde_json::Value::Object(fields) = data {
+        for (key, value) in fields {
+            context.insert(key.as_str(), value);
+        }
     }
+    // The layout's own values win over upstream fields of the same name.
+    context.extend(page_context());
+    let pretty = serde_json::to_string_pretty(data).unwrap_or_default();
+    context.insert("title", title);
+    context.insert(
+        "body",
+        &format!("<pre>{}</pre>", html_escape::encode_safe(&pretty)),
+    );
+    context.insert("data", data);
+
+    get_tera().render(template_name, &context).map_err(|err| {
+        tracing::error!("Template rendering failed for {}: {:?}", template_name, err);
+        format!("Template rendering failed for {}", template_name)
+    })
 }
 
 /// a generic HTML response.
@@ -1,26 +1,37 @@
+pub mod reader;
+pub mod viewer;
+
 use tracing::Event;
 use tracing::Subscriber;
 use tracing::{error, info, warn};
-use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::FormattedFields;
 use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
 use tracing_subscriber::fmt::time::FormatTime;
 use tracing_subscriber::fmt::time::UtcTime;
 use tracing_subscriber::registry::LookupSpan;
-use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{EnvFilter, Registry, reload};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
+use flate2::{Compression, write::GzEncoder};
 use std::io::{Seek, SeekFrom};
 use std::{
     fs,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::OnceLock,
     time::SystemTime,
 };
+use time::{OffsetDateTime, macros::format_description};
 
-const MAX_LOG_SIZE: u64 = 8 * 1024 * 1024; // 8 MB
-const MAX_LOG_FILES: usize = 5;
+use crate::config::{ArchiveNaming, LogFormat, LogRotationConfig, LoggingConfig, RotationSchedule};
 
 /// A file appender with the ability to rotate log files should they
-/// exceed a maximum size.
+/// exceed a maximum size, or on an hourly/daily schedule.
+///
+/// Rotated files are kept as numbered (`my_app.1.log` is the newest) or
+/// timestamped archives, optionally gzipped, and only the newest
+/// `max_files` archives are kept.  A `max_files` of 0 is treated as 1, so
+/// the archive just rotated out is never deleted.
 ///
 /// `SizeRotatingWriter` implements the [`std:io::Write` trait][write] and will
 /// block on write operations.
@@ -35,11 +46,19 @@ pub struct SizeRotatingWriter {
     base_path: PathBuf,
     current_file: fs::File,
     current_size: u64,
+    /// Start of the rotation period the current file belongs to.
+    current_period: Option<i64>,
     prefix: String,
+    rotation: LogRotationConfig,
 }
 
 impl SizeRotatingWriter {
-    pub fn new(log_dir: impl Into<PathBuf>, prefix: &str) -> io::Result<Self> {
+    /// Open `<log_dir>/<prefix>.log`, rotating it according to `rotation`.
+    pub fn new(
+        log_dir: impl Into<PathBuf>,
+        prefix: &str,
+        rotation: LogRotationConfig,
+    ) -> io::Result<Self> {
         let dir = log_dir.into();
         fs::create_dir_all(&dir)?;
 
@@ -51,35 +70,97 @@ impl SizeRotatingWriter {
 
         let size = file.seek(SeekFrom::End(0))?;
 
+        // An existing file belongs to the period it was last written in,
+        // so a restart doesn't skip a scheduled rotation.
+        let modified = file
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        let current_period = period_start(&rotation.rotate_every, modified);
+
         Ok(Self {
             base_path: dir,
             current_file: file,
             current_size: size,
+            current_period,
             prefix: prefix.to_string(),
+            rotation,
         })
     }
 
-    fn rotate(&mut self) -> io::Result<()> {
-        // Close current file and rename with timestamp or number
-        let mut rotated_files: Vec<_> = fs::read_dir(&self.base_path)?
+    fn current_log(&self) -> PathBuf {
+        self.base_path.join(format!("{}.log", self.prefix))
+    }
+
+    /// Path of numbered archive `n`, uncompressed.  Callers add `.gz` with
+    /// `with_suffix`.
+    fn numbered_archive(&self, n: usize) -> PathBuf {
+        self.base_path.join(format!("{}.{}.log", self.prefix, n))
+    }
+
+    /// All rotated archives of this log, oldest first.
+    fn archives(&self) -> io::Result<Vec<PathBuf>> {
+        let mut archives: Vec<PathBuf> = fs::read_dir(&self.base_path)?
             .filter_map(Result::ok)
-            .filter(|e| e.file_name().to_string_lossy().starts_with(&self.prefix))
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| is_archive_of(n, &self.prefix))
+            })
             .collect();
 
-        rotated_files.sort_by_key(|e| {
-            e.metadata()
+        archives.sort_by_key(|p| {
+            fs::metadata(p)
                 .and_then(|m| m.modified())
                 .unwrap_or(SystemTime::UNIX_EPOCH)
         });
-        while rotated_files.len() >= MAX_LOG_FILES {
-            let oldest = rotated_files.remove(0);
-            let _ = fs::remove_file(oldest.path());
-        }
+        Ok(archives)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.current_file.flush()?;
+        let current_log = self.current_log();
 
-        let new_name = self.base_path.join(format!("{}.log", self.prefix));
-        let current_log = self.base_path.join(format!("{}.log", self.prefix));
+        let archived = match self.rotation.naming {
+            ArchiveNaming::Numbered => {
+                // Shift my_app.N.log -> my_app.N+1.log, dropping the oldest.
+                let max = self.rotation.max_files.max(1);
+                for suffix in ["", ".gz"] {
+                    let oldest = with_suffix(self.numbered_archive(max), suffix);
+                    let _ = fs::remove_file(oldest);
+                }
+                for n in (1..max).rev() {
+                    for suffix in ["", ".gz"] {
+                        let from = with_suffix(self.numbered_archive(n), suffix);
+                        if from.exists() {
+                            fs::rename(&from, with_suffix(self.numbered_archive(n + 1), suffix))?;
+                        }
+                    }
+                }
+                self.numbered_archive(1)
+            }
+            ArchiveNaming::Timestamp => {
+                let stamp = OffsetDateTime::now_utc()
+                    .format(format_description!(
+                        "[year][month][day]T[hour][minute][second]"
+                    ))
+                    .unwrap_or_default();
+                let mut archived = self
+                    .base_path
+                    .join(format!("{}.{}.log", self.prefix, stamp));
+                let mut n = 1;
+                while archived.exists() || with_suffix(archived.clone(), ".gz").exists() {
+                    archived = self
+                        .base_path
+                        .join(format!("{}.{}-{}.log", self.prefix, stamp, n));
+                    n += 1;
+                }
+                archived
+            }
+        };
 
-        fs::rename(&current_log, &new_name)?;
+        fs::rename(&current_log, &archived)?;
 
         // Open new file
         self.current_file = fs::OpenOptions::new()
@@ -87,16 +168,46 @@ impl SizeRotatingWriter {
             .write(true)
             .truncate(true)
             .open(&current_log)?;
-
         self.current_size = 0;
+        self.current_period = period_start(&self.rotation.rotate_every, SystemTime::now());
+
+        if self.rotation.compress
+            && let Err(e) = gzip_file(&archived)
+        {
+            eprintln!("Failed to compress {}: {}", archived.display(), e);
+        }
+
+        // Timestamped archives aren't shifted out, so prune the oldest here.
+        if let ArchiveNaming::Timestamp = self.rotation.naming {
+            let mut archives = self.archives()?;
+            let max = self.rotation.max_files.max(1);
+            while archives.len() > max {
+                let oldest = archives.remove(0);
+                let _ = fs::remove_file(oldest);
+            }
+        }
 
         Ok(())
     }
+
+    fn needs_rotation(&mut self, incoming: u64) -> bool {
+        let now = period_start(&self.rotation.rotate_every, SystemTime::now());
+        if self.current_size == 0 {
+            // Nothing to archive; an empty file left from an earlier
+            // period just starts this one.
+            self.current_period = now;
+            return false;
+        }
+        if self.current_size + incoming > self.rotation.max_size_mb * 1024 * 1024 {
+            return true;
+        }
+        now.is_some() && now != self.current_period
+    }
 }
 
 impl Write for SizeRotatingWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if self.current_size + buf.len() as u64 > MAX_LOG_SIZE {
+        if self.needs_rotation(buf.len() as u64) {
             self.rotate()?;
         }
 
@@ -110,6 +221,75 @@ impl Write for SizeRotatingWriter {
     }
 }
 
+/// Is `file_name` a rotated archive of the `<prefix>.log` file?
+/// Matches `<prefix>.<N>.log` and `<prefix>.<timestamp>.log`, each with an
+/// optional `.gz`.
+pub fn is_archive_of(file_name: &str, prefix: &str) -> bool {
+    is_archive_named(file_name, prefix, ".log")
+}
+
+/// Is `file_name` `<stem>.<N><ext>` or `<stem>.<timestamp><ext>`, with an
+/// optional `.gz`?  `ext` may be empty for logrotate style `app.log.1`.
+pub fn is_archive_named(file_name: &str, stem: &str, ext: &str) -> bool {
+    let Some(rest) = file_name
+        .strip_prefix(stem)
+        .and_then(|r| r.strip_prefix('.'))
+    else {
+        return false;
+    };
+    let rest = rest.strip_suffix(".gz").unwrap_or(rest);
+    rest.strip_suffix(ext).is_some_and(is_archive_tag)
+}
+
+/// The part `rotate` puts between the prefix and `.log`: a rotation number
+/// or a `YYYYMMDDTHHMMSS` stamp with an optional `-N` tiebreaker.
+fn is_archive_tag(tag: &str) -> bool {
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if all_digits(tag) {
+        return true;
+    }
+    let (stamp, tiebreak) = match tag.split_once('-') {
+        Some((stamp, n)) => (stamp, Some(n)),
+        None => (tag, None),
+    };
+    let stamp_ok = stamp.len() == 15
+        && stamp.as_bytes()[8] == b'T'
+        && all_digits(&stamp[..8])
+        && all_digits(&stamp[9..]);
+    stamp_ok && tiebreak.is_none_or(all_digits)
+}
+
+fn with_suffix(path: PathBuf, suffix: &str) -> PathBuf {
+    let mut name = path.into_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Gzip `path` into `path.gz` and remove the original.
+fn gzip_file(path: &Path) -> io::Result<()> {
+    let gz_path = with_suffix(path.to_path_buf(), ".gz");
+    let mut input = fs::File::open(path)?;
+    let output = fs::File::create(&gz_path)?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Start (unix seconds, UTC) of the rotation period containing `time`.
+/// `None` when there is no time based rotation.
+fn period_start(schedule: &RotationSchedule, time: SystemTime) -> Option<i64> {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    match schedule {
+        RotationSchedule::Never => None,
+        RotationSchedule::Hourly => Some(secs - secs.rem_euclid(3600)),
+        RotationSchedule::Daily => Some(secs - secs.rem_euclid(86400)),
+    }
+}
+
 pub struct CustomFormatter<T> {
     pub timer: T,
 }
@@ -137,36 +317,113 @@ where
             meta.target()
         )?;
 
+        // Span context, e.g. request{request_id=...}: so lines can be
+        // linked back to the request that caused them.
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "{}", span.name())?;
+                let ext = span.extensions();
+                if let Some(fields) = ext.get::<FormattedFields<N>>()
+                    && !fields.is_empty()
+                {
+                    write!(writer, "{{{}}}", fields)?;
+                }
+                write!(writer, ": ")?;
+            }
+        }
+
         ctx.field_format().format_fields(writer.by_ref(), event)?;
         writeln!(writer)
     }
 }
 
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static LOG_CONFIG: OnceLock<LoggingConfig> = OnceLock::new();
+
+/// The logging configuration the server was started with.
+pub fn log_config() -> &'static LoggingConfig {
+    LOG_CONFIG.get_or_init(LoggingConfig::default)
+}
+
 /// Setup the logging system
-pub fn init_logging() {
-    let writer = SizeRotatingWriter::new("./logs", "my_app").expect("Failed to init log writer");
+pub fn init_logging(config: &LoggingConfig) {
+    let _ = LOG_CONFIG.set(config.clone());
+    let writer = SizeRotatingWriter::new(&config.dir, &config.prefix, config.rotation.clone())
+        .expect("Failed to init log writer");
     let (non_blocking, guard) = tracing_appender::non_blocking(writer);
 
     // Store the guard to keep the background logging thread alive
     // If dropped, logs will stop being written!
     Box::leak(Box::new(guard)); // <- safest simple method
 
-    let custom_format = CustomFormatter {
-        timer: UtcTime::rfc_3339(),
+    // RUST_LOG wins over the config file.
+    let directives =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| config.filter.clone());
+    let (filter, filter_error) = match EnvFilter::try_new(&directives) {
+        Ok(filter) => (filter, None),
+        Err(e) => (EnvFilter::new("info"), Some(e)),
     };
-    tracing_subscriber::registry()
-        .with(
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let text_layer = match config.format {
+        LogFormat::Text => Some(
             fmt::layer()
-                .with_writer(non_blocking)
+                .with_writer(non_blocking.clone())
                 .with_target(true) // includes module path
                 .with_ansi(false) // disable colors for file
-                .event_format(custom_format)
-                .with_filter(LevelFilter::INFO),
-        )
+                .event_format(CustomFormatter {
+                    timer: UtcTime::rfc_3339(),
+                }),
+        ),
+        LogFormat::Json => None,
+    };
+    let json_layer = match config.format {
+        LogFormat::Json => Some(
+            fmt::layer()
+                .json()
+                .with_writer(non_blocking)
+                .with_timer(UtcTime::rfc_3339())
+                .with_current_span(true)
+                .with_span_list(false),
+        ),
+        LogFormat::Text => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text_layer)
+        .with(json_layer)
         .init();
 
-    // Log test messages
-    info!("This is an info message.");
-    warn!("This is a warning.");
-    error!("This is an error.");
+    if FILTER_HANDLE.set(handle).is_err() {
+        warn!("Log filter handle was already set");
+    }
+    if let Some(e) = filter_error {
+        error!(
+            "Invalid log filter '{}', falling back to info: {}",
+            directives, e
+        );
+    }
+    info!(
+        "Logging started with filter '{}'",
+        current_filter().unwrap_or_default()
+    );
+}
+
+/// The log filter directives currently in effect.
+pub fn current_filter() -> Option<String> {
+    FILTER_HANDLE
+        .get()?
+        .with_current(|filter| filter.to_string())
+        .ok()
+}
+
+/// Replace the log filter at runtime with new RUST_LOG style directives.
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    FILTER_HANDLE
+        .get()
+        .ok_or_else(|| "Logging is not initialized".to_string())?
+        .reload(filter)
+        .map_err(|e| e.to_string())
 }
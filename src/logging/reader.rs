@@ -0,0 +1,204 @@
+/*
+
+Native log file reading: newest-first line iteration and following a
+file as it grows, surviving rotation and truncation.
+
+*/
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+const CHUNK_SIZE: u64 = 16 * 1024;
+
+/// Iterator over the lines of a file, newest (last) line first.
+///
+/// Reads the file backwards in chunks, so only as much of the file as
+/// is consumed is ever read.  Empty lines are skipped.
+pub struct ReverseLines {
+    file: fs::File,
+    /// Bytes before `pos` have not been read yet.
+    pos: u64,
+    /// Partial line at the start of the last chunk read.
+    carry: Vec<u8>,
+    /// Complete lines from the last chunk, oldest first.
+    pending: Vec<String>,
+}
+
+impl ReverseLines {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let pos = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            pos,
+            carry: Vec::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let start = self.pos.saturating_sub(CHUNK_SIZE);
+        let mut chunk = vec![0; (self.pos - start) as usize];
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.read_exact(&mut chunk)?;
+        self.pos = start;
+
+        chunk.append(&mut self.carry);
+        let mut pieces = chunk.split(|b| *b == b'\n');
+        // The first piece may continue into the previous chunk.
+        self.carry = pieces.next().unwrap_or_default().to_vec();
+        self.pending = pieces.filter_map(line_from_bytes).collect();
+        Ok(())
+    }
+}
+
+impl Iterator for ReverseLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self.pending.pop() {
+                return Some(Ok(line));
+            }
+            if self.pos == 0 {
+                let carry = std::mem::take(&mut self.carry);
+                return line_from_bytes(&carry).map(Ok);
+            }
+            if let Err(e) = self.read_chunk() {
+                self.pos = 0;
+                self.carry.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn line_from_bytes(bytes: &[u8]) -> Option<String> {
+    let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    if bytes.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Lines of a log file, newest first.
+///
+/// Gzipped archives (`.gz`) are decompressed in memory, everything else
+/// is read backwards from the end with [`ReverseLines`].
+pub fn lines_newest_first(path: &Path) -> io::Result<Box<dyn Iterator<Item = String>>> {
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut text = String::new();
+        GzDecoder::new(fs::File::open(path)?).read_to_string(&mut text)?;
+        let lines: Vec<String> = text
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect();
+        Ok(Box::new(lines.into_iter().rev()))
+    } else {
+        Ok(Box::new(ReverseLines::open(path)?.map_while(Result::ok)))
+    }
+}
+
+/// Follows a file like `tail -F`.
+///
+/// Starts at the current end of the file and returns lines appended
+/// since the last poll.  If the file is truncated, or replaced by a new
+/// file (as happens on rotation), it is reopened and read from the start.
+pub struct LogFollower {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    identity: Option<FileIdentity>,
+    pos: u64,
+    partial: Vec<u8>,
+}
+
+#[derive(PartialEq)]
+struct FileIdentity(u64, u64);
+
+#[cfg(unix)]
+fn file_identity(meta: &fs::Metadata) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileIdentity(meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_meta: &fs::Metadata) -> Option<FileIdentity> {
+    None
+}
+
+impl LogFollower {
+    /// Start following `path` from its current end.
+    pub async fn new(path: impl Into<PathBuf>) -> Self {
+        let mut follower = Self {
+            path: path.into(),
+            file: None,
+            identity: None,
+            pos: 0,
+            partial: Vec::new(),
+        };
+        if follower.reopen().await.is_ok()
+            && let Some(file) = follower.file.as_mut()
+        {
+            follower.pos = file.seek(SeekFrom::End(0)).await.unwrap_or(0);
+        }
+        follower
+    }
+
+    async fn reopen(&mut self) -> io::Result<()> {
+        self.file = None;
+        self.pos = 0;
+        self.partial.clear();
+        let file = tokio::fs::File::open(&self.path).await?;
+        self.identity = file_identity(&file.metadata().await?);
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Read any complete lines appended since the last call.
+    pub async fn poll_lines(&mut self) -> io::Result<Vec<String>> {
+        // Has the path been rotated away, or the file truncated?
+        let replaced = match tokio::fs::metadata(&self.path).await {
+            Ok(meta) => {
+                self.file.is_none()
+                    || file_identity(&meta) != self.identity
+                    || meta.len() < self.pos
+            }
+            Err(_) => return Ok(Vec::new()), // gone for now, try again later
+        };
+
+        let mut lines = Vec::new();
+        if replaced {
+            // Pick up whatever was written to the old file before it moved.
+            if self.file.is_some() {
+                lines.extend(self.read_new().await?);
+            }
+            self.reopen().await?;
+        }
+        lines.extend(self.read_new().await?);
+        Ok(lines)
+    }
+
+    async fn read_new(&mut self) -> io::Result<Vec<String>> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(Vec::new());
+        };
+        file.seek(SeekFrom::Start(self.pos)).await?;
+        let mut buf = Vec::new();
+        let read = file.read_to_end(&mut buf).await?;
+        self.pos += read as u64;
+
+        self.partial.extend_from_slice(&buf);
+        let Some(last_newline) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=last_newline).collect();
+        Ok(complete
+            .split(|b| *b == b'\n')
+            .filter_map(line_from_bytes)
+            .collect())
+    }
+}
@@ -0,0 +1,390 @@
+/*
+
+Protected viewer for the server's own log (my_app.log and its archives).
+
+Pages through entries newest first, filters by level, target and time
+range, tails the live log over SSE and downloads whole files.
+
+*/
+use axum::{
+    Json, Router,
+    extract::Query,
+    http::{StatusCode, header},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::Duration;
+use time::{OffsetDateTime, PrimitiveDateTime, format_description::well_known::Rfc3339};
+
+use crate::auth::users::AuthSession;
+use crate::htmlv::{get_tera, page_context};
+use crate::logging::{is_archive_of, log_config, reader};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// One parsed line of the server log.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub time: Option<String>,
+    pub level: Option<String>,
+    pub target: Option<String>,
+    pub message: String,
+}
+
+/// Parse a line written by `CustomFormatter`:
+///
+/// `[LINE] [2025-01-01T00:00:00.000Z] [INFO    ] target: spans: message`
+///
+/// or by the JSON log format.  Lines in neither format (such as the
+/// continuation of a multi-line message) come back with only a message.
+pub fn parse_log_line(line: &str) -> LogEntry {
+    parse_text_line(line)
+        .or_else(|| parse_json_line(line))
+        .unwrap_or_else(|| LogEntry {
+            time: None,
+            level: None,
+            target: None,
+            message: line.to_string(),
+        })
+}
+
+fn parse_text_line(line: &str) -> Option<LogEntry> {
+    let rest = line.strip_prefix("[LINE] [")?;
+    let (time, rest) = rest.split_once("] [")?;
+    let (level, rest) = rest.split_once("] ")?;
+    let (target, message) = rest.split_once(": ").unwrap_or((rest, ""));
+    Some(LogEntry {
+        time: Some(time.to_string()),
+        level: Some(level.trim().to_string()),
+        target: Some(target.to_string()),
+        message: message.to_string(),
+    })
+}
+
+fn parse_json_line(line: &str) -> Option<LogEntry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let get_str = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+
+    let mut message = value
+        .pointer("/fields/message")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    // Keep any other structured fields visible.
+    if let Some(fields) = value.get("fields").and_then(|f| f.as_object()) {
+        for (key, field) in fields.iter().filter(|(k, _)| *k != "message") {
+            message.push_str(&format!(" {}={}", key, field));
+        }
+    }
+    if let Some(span) = value.get("span") {
+        message = format!("{} {}", span, message);
+    }
+
+    Some(LogEntry {
+        time: get_str("timestamp"),
+        level: get_str("level"),
+        target: get_str("target"),
+        message,
+    })
+}
+
+fn level_rank(level: &str) -> Option<u8> {
+    match level.trim().to_ascii_uppercase().as_str() {
+        "TRACE" => Some(0),
+        "DEBUG" => Some(1),
+        "INFO" => Some(2),
+        "WARN" => Some(3),
+        "ERROR" => Some(4),
+        _ => None,
+    }
+}
+
+/// Accepts RFC 3339, or the `YYYY-MM-DDTHH:MM` a datetime-local input sends (as UTC).
+pub fn parse_time_param(value: &str) -> Option<OffsetDateTime> {
+    if let Ok(time) = OffsetDateTime::parse(value, &Rfc3339) {
+        return Some(time);
+    }
+    let local = time::macros::format_description!("[year]-[month]-[day]T[hour]:[minute]");
+    let local_secs =
+        time::macros::format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+    PrimitiveDateTime::parse(value, local)
+        .or_else(|_| PrimitiveDateTime::parse(value, local_secs))
+        .ok()
+        .map(|t| t.assume_utc())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogQuery {
+    /// file- the log or archive file name.  Defaults to the current log.
+    file: Option<String>,
+    /// level- minimum level to show.
+    level: Option<String>,
+    /// target- only entries whose target contains this.
+    target: Option<String>,
+    /// since/until- time range.
+    since: Option<String>,
+    until: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// Entry filter built from a `LogQuery`.
+struct EntryFilter {
+    min_level: Option<u8>,
+    target: Option<String>,
+    since: Option<OffsetDateTime>,
+    until: Option<OffsetDateTime>,
+}
+
+impl EntryFilter {
+    fn from_query(query: &LogQuery) -> Result<Self, String> {
+        let min_level = match query.level.as_deref().filter(|l| !l.is_empty()) {
+            Some(level) => {
+                Some(level_rank(level).ok_or_else(|| format!("Unknown level '{}'", level))?)
+            }
+            None => None,
+        };
+        let time_param = |value: &Option<String>| match value.as_deref().filter(|v| !v.is_empty()) {
+            Some(v) => parse_time_param(v)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid time '{}'", v)),
+            None => Ok(None),
+        };
+        Ok(Self {
+            min_level,
+            target: query.target.clone().filter(|t| !t.is_empty()),
+            since: time_param(&query.since)?,
+            until: time_param(&query.until)?,
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(min) = self.min_level
+            && entry
+                .level
+                .as_deref()
+                .and_then(level_rank)
+                .is_none_or(|rank| rank < min)
+        {
+            return false;
+        }
+        if let Some(target) = &self.target
+            && !entry.target.as_deref().is_some_and(|t| t.contains(target))
+        {
+            return false;
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(time) = entry
+                .time
+                .as_deref()
+                .and_then(|t| OffsetDateTime::parse(t, &Rfc3339).ok())
+            else {
+                return false;
+            };
+            if self.since.is_some_and(|since| time < since)
+                || self.until.is_some_and(|until| time > until)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Resolve a file name from the query to the current log or one of its
+/// archives.  Anything else is rejected.
+fn resolve_log_file(file: Option<&str>) -> Option<PathBuf> {
+    let config = log_config();
+    let current = format!("{}.log", config.prefix);
+    let name = file.filter(|f| !f.is_empty()).unwrap_or(&current);
+    if name.contains(['/', '\\']) || (name != current && !is_archive_of(name, &config.prefix)) {
+        return None;
+    }
+    Some(PathBuf::from(&config.dir).join(name))
+}
+
+/// The current log and its archives: (name, size in bytes), newest first.
+fn list_log_files() -> Vec<(String, u64)> {
+    let config = log_config();
+    let current = format!("{}.log", config.prefix);
+    let mut files: Vec<(String, u64, std::time::SystemTime)> = std::fs::read_dir(&config.dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    if name != current && !is_archive_of(&name, &config.prefix) {
+                        return None;
+                    }
+                    let meta = e.metadata().ok()?;
+                    Some((name, meta.len(), meta.modified().ok()?))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by_key(|file| std::cmp::Reverse(file.2));
+    files
+        .into_iter()
+        .map(|(name, size, _)| (name, size))
+        .collect()
+}
+
+fn error_json(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({ "status": "error", "message": message.into() })),
+    )
+        .into_response()
+}
+
+/// The viewer page.
+pub async fn viewer_page(auth_session: AuthSession) -> impl IntoResponse {
+    let Some(user) = auth_session.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let files: Vec<Value> = list_log_files()
+        .into_iter()
+        .map(|(name, size)| json!({ "name": name, "size": size }))
+        .collect();
+
+    let mut context = page_context();
+    context.insert("title", "Server Logs");
+    context.insert("username", &user.username);
+    context.insert("files", &files);
+    match get_tera().render("server_logs.html", &context) {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            tracing::error!("Template rendering failed for server_logs.html: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// A page of entries, newest first, as JSON.
+pub async fn entries_handler(Query(query): Query<LogQuery>) -> Response {
+    let filter = match EntryFilter::from_query(&query) {
+        Ok(filter) => filter,
+        Err(e) => return error_json(StatusCode::BAD_REQUEST, e),
+    };
+    let Some(path) = resolve_log_file(query.file.as_deref()) else {
+        return error_json(StatusCode::NOT_FOUND, "Unknown log file");
+    };
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let result = tokio::task::spawn_blocking(move || {
+        let lines = reader::lines_newest_first(&path)?;
+        let mut matching = lines
+            .map(|line| parse_log_line(&line))
+            .filter(|entry| filter.matches(entry))
+            .skip(offset);
+        let entries: Vec<LogEntry> = matching.by_ref().take(limit).collect();
+        let has_more = matching.next().is_some();
+        Ok::<_, std::io::Error>((entries, has_more))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((entries, has_more))) => Json(json!({
+            "file": query.file,
+            "offset": offset,
+            "limit": limit,
+            "has_more": has_more,
+            "entries": entries,
+        }))
+        .into_response(),
+        Ok(Err(e)) => error_json(StatusCode::NOT_FOUND, e.to_string()),
+        Err(e) => error_json(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Stream new entries of the current log as server-sent events.
+pub async fn tail_handler(Query(query): Query<LogQuery>) -> Response {
+    let filter = match EntryFilter::from_query(&query) {
+        Ok(filter) => filter,
+        Err(e) => return error_json(StatusCode::BAD_REQUEST, e),
+    };
+    let Some(path) = resolve_log_file(None) else {
+        return error_json(StatusCode::NOT_FOUND, "Unknown log file");
+    };
+
+    let follower = reader::LogFollower::new(path).await;
+    let events = stream::unfold(
+        (follower, filter, VecDeque::<LogEntry>::new()),
+        |(mut follower, filter, mut queue)| async move {
+            while queue.is_empty() {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                match follower.poll_lines().await {
+                    Ok(lines) => queue.extend(
+                        lines
+                            .iter()
+                            .map(|line| parse_log_line(line))
+                            .filter(|entry| filter.matches(entry)),
+                    ),
+                    Err(e) => tracing::debug!("Log tail poll failed: {}", e),
+                }
+            }
+            let entry = queue.pop_front()?;
+            let event = Event::default()
+                .json_data(&entry)
+                .unwrap_or_else(|_| Event::default().data(entry.message.clone()));
+            Some((Ok::<_, Infallible>(event), (follower, filter, queue)))
+        },
+    );
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Download the whole log file (or archive).
+pub async fn download_handler(Query(query): Query<LogQuery>) -> Response {
+    let Some(path) = resolve_log_file(query.file.as_deref()) else {
+        return error_json(StatusCode::NOT_FOUND, "Unknown log file");
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => {
+            let content_type = if name.ends_with(".gz") {
+                "application/gzip"
+            } else {
+                "text/plain; charset=utf-8"
+            };
+            (
+                [
+                    (header::CONTENT_TYPE, content_type.to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", name),
+                    ),
+                ],
+                bytes,
+            )
+                .into_response()
+        }
+        Err(e) => error_json(StatusCode::NOT_FOUND, e.to_string()),
+    }
+}
+
+/// Routes for the viewer, nested under /protected/server_logs.
+pub fn router() -> Router<()> {
+    Router::new()
+        .route("/", get(viewer_page))
+        .route("/entries", get(entries_handler))
+        .route("/tail", get(tail_handler))
+        .route("/download", get(download_handler))
+}
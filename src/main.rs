@@ -9,39 +9,58 @@
 //! }
 //! ```
 
+mod access_log;
 mod add_user;
 mod app;
+mod audit;
 mod auth;
 mod certs;
+mod check;
 mod config;
 mod htmlv;
 mod logging;
+mod metrics;
 mod my_api_config;
 mod myapi;
 mod procmon;
+mod secrets;
 mod state;
 
 use add_user::adduser_from_prompt;
 use app::RustyWebApp;
+use config::load_or_create_config;
 use state::AppSingleton;
 use std::env;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     AppSingleton::init();
-    logging::init_logging();
+    let config = load_or_create_config("config.yaml");
+    logging::init_logging(&config.logging);
 
     let args: Vec<String> = env::args().collect();
 
-    if let Some(cmd) = args.get(1) {
-        if cmd == "add-user" {
-            println!("Adding user...");
+    if let Some(cmd) = args.get(1)
+        && cmd == "add-user"
+    {
+        println!("Adding user...");
 
-            return adduser_from_prompt().await;
-        }
+        return adduser_from_prompt().await;
+    }
+
+    if let Some(cmd) = args.get(1)
+        && cmd == "secret"
+    {
+        return secrets::secret_command(&args[2..]).await;
+    }
+
+    if let Some(cmd) = args.get(1)
+        && cmd == "check"
+    {
+        return check::check_command();
     }
 
     // default application launch
-    RustyWebApp::new().await?.run().await?;
+    RustyWebApp::new(config).await?.run().await?;
     Ok(())
 }
@@ -0,0 +1,299 @@
+/*
+
+Prometheus metrics, served in the text exposition format at /metrics.
+Request, script and call_api upstream metrics are collected as they
+happen; procmon gauges and the session count are read at scrape time.
+
+*/
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{Request, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Upper bounds in seconds for request and upstream latencies.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds in seconds for script run times.
+const SCRIPT_BUCKETS: [f64; 8] = [1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0, 14400.0];
+
+/// Label for requests that matched no route, so arbitrary URLs can't
+/// create new series.
+const OTHER_ROUTE: &str = "other";
+
+static METRICS_DB: OnceLock<SqlitePool> = OnceLock::new();
+static METRICS: Lazy<Mutex<Metrics>> = Lazy::new(|| Mutex::new(Metrics::default()));
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Series are kept sorted by their labels, so scrapes are stable.
+#[derive(Default)]
+struct Metrics {
+    /// (route, method, status)
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// route
+    request_seconds: BTreeMap<String, Histogram>,
+    /// (script, exit status)
+    script_seconds: BTreeMap<(String, String), Histogram>,
+    /// upstream host
+    upstream_seconds: BTreeMap<String, Histogram>,
+    /// (upstream host, kind)
+    upstream_errors: BTreeMap<(String, String), u64>,
+}
+
+/// Set the database the session count is read from.  Should be called
+/// once at startup.
+pub fn init_metrics(db: SqlitePool) {
+    if METRICS_DB.set(db).is_err() {
+        tracing::warn!("Metrics were already initialized");
+    }
+}
+
+/// Middleware that counts requests and times them, by route.
+pub async fn track_requests(req: Request<Body>, next: Next) -> Response {
+    let start = Instant::now();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(OTHER_ROUTE, |p| p.as_str())
+        .to_string();
+    let method = req.method().to_string();
+
+    let response = next.run(req).await;
+
+    let seconds = start.elapsed().as_secs_f64();
+    let status = response.status().as_u16();
+    let mut metrics = METRICS.lock().unwrap();
+    *metrics
+        .requests
+        .entry((route.clone(), method, status))
+        .or_default() += 1;
+    metrics
+        .request_seconds
+        .entry(route)
+        .or_insert_with(|| Histogram::new(&LATENCY_BUCKETS))
+        .observe(seconds);
+    response
+}
+
+/// Record a finished script run.  `status` is its exit code, "signal" or "killed".
+pub fn observe_script(script: &str, status: &str, seconds: f64) {
+    METRICS
+        .lock()
+        .unwrap()
+        .script_seconds
+        .entry((script.to_string(), status.to_string()))
+        .or_insert_with(|| Histogram::new(&SCRIPT_BUCKETS))
+        .observe(seconds);
+}
+
+/// Record a call_api upstream call, and its error kind if it failed.
+pub fn observe_upstream(host: &str, seconds: f64, error: Option<&str>) {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics
+        .upstream_seconds
+        .entry(host.to_string())
+        .or_insert_with(|| Histogram::new(&LATENCY_BUCKETS))
+        .observe(seconds);
+    if let Some(kind) = error {
+        *metrics
+            .upstream_errors
+            .entry((host.to_string(), kind.to_string()))
+            .or_default() += 1;
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `{a="1",b="2"}` from label pairs, or nothing without labels.
+pub fn labels(pairs: &[(&str, &str)]) -> String {
+    if pairs.is_empty() {
+        return String::new();
+    }
+    let inner: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
+
+/// Write the HELP and TYPE lines of a metric.
+pub fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histogram(out: &mut String, name: &str, pairs: &[(&str, &str)], histogram: &Histogram) {
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        let le = bound.to_string();
+        let mut with_le = pairs.to_vec();
+        with_le.push(("le", &le));
+        let _ = writeln!(out, "{}_bucket{} {}", name, labels(&with_le), count);
+    }
+    let mut with_le = pairs.to_vec();
+    with_le.push(("le", "+Inf"));
+    let _ = writeln!(
+        out,
+        "{}_bucket{} {}",
+        name,
+        labels(&with_le),
+        histogram.count
+    );
+    let _ = writeln!(out, "{}_sum{} {}", name, labels(pairs), histogram.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, labels(pairs), histogram.count);
+}
+
+fn write_collected(out: &mut String) {
+    let metrics = METRICS.lock().unwrap();
+
+    header(
+        out,
+        "http_requests_total",
+        "counter",
+        "HTTP requests by route, method and status.",
+    );
+    for ((route, method, status), count) in &metrics.requests {
+        let status = status.to_string();
+        let pairs = [
+            ("route", route.as_str()),
+            ("method", method),
+            ("status", &status),
+        ];
+        let _ = writeln!(out, "http_requests_total{} {}", labels(&pairs), count);
+    }
+
+    header(
+        out,
+        "http_request_duration_seconds",
+        "histogram",
+        "HTTP request latency by route.",
+    );
+    for (route, histogram) in &metrics.request_seconds {
+        write_histogram(
+            out,
+            "http_request_duration_seconds",
+            &[("route", route)],
+            histogram,
+        );
+    }
+
+    header(
+        out,
+        "script_run_duration_seconds",
+        "histogram",
+        "Script run times by script and exit status.",
+    );
+    for ((script, status), histogram) in &metrics.script_seconds {
+        write_histogram(
+            out,
+            "script_run_duration_seconds",
+            &[("script", script), ("exit_status", status)],
+            histogram,
+        );
+    }
+
+    header(
+        out,
+        "call_api_upstream_duration_seconds",
+        "histogram",
+        "call_api upstream call latency by host.",
+    );
+    for (host, histogram) in &metrics.upstream_seconds {
+        write_histogram(
+            out,
+            "call_api_upstream_duration_seconds",
+            &[("host", host)],
+            histogram,
+        );
+    }
+
+    header(
+        out,
+        "call_api_upstream_errors_total",
+        "counter",
+        "call_api upstream failures by host and kind (timeout, unreachable or status).",
+    );
+    for ((host, kind), count) in &metrics.upstream_errors {
+        let pairs = [("host", host.as_str()), ("kind", kind.as_str())];
+        let _ = writeln!(
+            out,
+            "call_api_upstream_errors_total{} {}",
+            labels(&pairs),
+            count
+        );
+    }
+}
+
+async fn active_sessions() -> Option<i64> {
+    let db = METRICS_DB.get()?;
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM tower_sessions WHERE datetime(expiry_date) > datetime('now')",
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| tracing::warn!("Failed to count sessions: {}", e))
+    .ok()
+}
+
+/// The /metrics endpoint.
+pub async fn metrics_handler() -> impl IntoResponse {
+    let mut out = crate::procmon::prometheus_gauges().await;
+    write_collected(&mut out);
+    if let Some(sessions) = active_sessions().await {
+        header(
+            &mut out,
+            "active_sessions",
+            "gauge",
+            "Unexpired login sessions.",
+        );
+        let _ = writeln!(out, "active_sessions {}", sessions);
+    }
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        out,
+    )
+}
@@ -1,23 +1,27 @@
-///! # API CONFIG
-///! Base Configuration of all possible RouteFunctions
-///! Definable in the JSON
-///! `RouteFunction` is an enum that represents different behaviors
-///! for handling HTTP requests, depending on the `function_type`
-///! specified in the associated data.
+//! # API CONFIG
+//! Base Configuration of all possible RouteFunctions
+//! Definable in the JSON
+//! `RouteFunction` is an enum that represents different behaviors
+//! for handling HTTP requests, depending on the `function_type`
+//! specified in the associated data.
 use serde::Deserialize;
 use std::collections::HashMap;
 
 use crate::auth::users::AuthSession;
+use crate::config::FixtureMode;
+use crate::htmlv::{TemplateRef, template_or_default};
+use crate::myapi::api_params::ParamRule;
 use crate::myapi::handlers::{
     api_caller_wrapped, get_logs_handler_wrapped, normal_page_template_handler,
     normal_page_template_handler_secure,
 };
+use crate::myapi::json_extract;
+use crate::myapi::markdown::render_markdown;
 use crate::myapi::shell_script_run::{
-    get_command_statuses, get_command_statuses_secure, run_command_handler,
-    run_command_handler_secure,
+    get_command_statuses, run_command_handler, run_command_handler_secure,
 };
 
-use axum::routing::get;
+use axum::routing::{MethodFilter, get, on};
 fn default_description() -> String {
     "No description, please set one for this route in /json_routes".to_string()
 }
@@ -43,6 +47,10 @@ pub struct RouteMeta {
     /// template_num- the html template number to use.  by default, it's 0.
     #[serde(default)]
     pub template_num: i32,
+    /// template- the html template by name, e.g. "template_plain", a name
+    /// from template_config.json or a file in ./templates.  Overrides template_num.
+    #[serde(default)]
+    pub template: Option<TemplateRef>,
     /// help_group- the category on the help page to be displayed under.
     #[serde(default)]
     pub help_group: String,
@@ -50,6 +58,95 @@ pub struct RouteMeta {
     /// auth_level- the authorization level required.  by default, it's 0.
     #[serde(default)]
     pub auth_level: i32,
+
+    /// nav- whether the route is linked from the nav bar.  by default, every
+    /// route but call_api ones.
+    #[serde(default)]
+    pub nav: Option<bool>,
+}
+
+impl RouteMeta {
+    /// The template this route asked for, by name or number.
+    pub fn template_ref(&self) -> TemplateRef {
+        self.template
+            .clone()
+            .unwrap_or(TemplateRef::Num(self.template_num))
+    }
+}
+
+/// HTTP methods usable by call_api routes, both for the upstream call
+/// and for the route exposed on our side.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ApiMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl ApiMethod {
+    pub fn as_reqwest(self) -> reqwest::Method {
+        match self {
+            ApiMethod::Get => reqwest::Method::GET,
+            ApiMethod::Post => reqwest::Method::POST,
+            ApiMethod::Put => reqwest::Method::PUT,
+            ApiMethod::Patch => reqwest::Method::PATCH,
+            ApiMethod::Delete => reqwest::Method::DELETE,
+        }
+    }
+
+    pub fn method_filter(self) -> MethodFilter {
+        match self {
+            ApiMethod::Get => MethodFilter::GET,
+            ApiMethod::Post => MethodFilter::POST,
+            ApiMethod::Put => MethodFilter::PUT,
+            ApiMethod::Patch => MethodFilter::PATCH,
+            ApiMethod::Delete => MethodFilter::DELETE,
+        }
+    }
+
+    /// Whether repeating the call is safe, so it may be retried.
+    pub fn is_idempotent(self) -> bool {
+        matches!(self, ApiMethod::Get | ApiMethod::Put | ApiMethod::Delete)
+    }
+}
+
+/// What a call_api route returns when the upstream answers with an error status.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamErrorMode {
+    /// The upstream's status, body and content type, as is.
+    #[default]
+    Passthrough,
+    /// A 502 with a JSON error holding the upstream's status and body.
+    Wrap,
+}
+
+/// What a call_api route does with an upstream response that is not JSON.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NonJsonMode {
+    /// Return it as is, with its content type.
+    #[default]
+    Proxy,
+    /// Wrap it as `{"content_type": ..., "base64": ...}`, so it can be
+    /// cached, extracted and templated like JSON.
+    Base64,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    250
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -59,6 +156,153 @@ pub struct ApiEndpointConfig {
     #[serde(default)]
     //The default parameters for this endpoint, if they exist at all
     pub default_params: HashMap<String, String>,
+    /// method- the HTTP method for the upstream call.  GET by default.
+    #[serde(default)]
+    pub method: ApiMethod,
+    /// headers- extra request headers.  Values may use `{{ param }}` placeholders.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// body- JSON body template.  String values may use `{{ param }}` placeholders;
+    /// a string that is only a placeholder takes the parameter's JSON type.
+    /// When set, parameters fill the body instead of the query string.
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    /// cache_ttl_secs- cache GET responses for this long, keyed on the full
+    /// request.  Capped by the upstream's Cache-Control max-age.
+    #[serde(default)]
+    pub cache_ttl_secs: Option<u64>,
+    /// stale_while_revalidate_secs- after the TTL, keep serving the cached
+    /// response for this long while it is refreshed in the background.
+    #[serde(default)]
+    pub stale_while_revalidate_secs: Option<u64>,
+    /// extract- reshape the upstream JSON before it is returned, either one
+    /// path (`"current.temperature_2m"`) or an object of output names to paths.
+    #[serde(default)]
+    pub extract: Option<ExtractConfig>,
+    /// template_num- render the (extracted) data through this template and
+    /// return HTML, unless the client asks for JSON with `Accept: application/json`.
+    #[serde(default)]
+    pub template_num: Option<i32>,
+    /// template- as template_num, by name.  Overrides template_num.
+    #[serde(default)]
+    pub template: Option<TemplateRef>,
+    /// The template file, resolved when the route is loaded.
+    #[serde(skip)]
+    pub resolved_template: Option<String>,
+    /// connect_timeout_secs- give up connecting to the upstream after this long.
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// timeout_secs- give up on the whole upstream call after this long.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// retries- extra attempts after a connection failure, timeout, 429, 502,
+    /// 503 or 504.  Only used for GET, PUT and DELETE.
+    #[serde(default)]
+    pub retries: u32,
+    /// retry_backoff_ms- wait before the first retry, doubled for each one after.
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// upstream_errors- `passthrough` (default) or `wrap`.
+    #[serde(default)]
+    pub upstream_errors: UpstreamErrorMode,
+    /// non_json- `proxy` (default) or `base64`.
+    #[serde(default)]
+    pub non_json: NonJsonMode,
+    /// params- the parameters clients may pass, with their rules.  When set,
+    /// any other parameter is rejected; otherwise all are passed on.
+    #[serde(default)]
+    pub params: Option<HashMap<String, ParamRule>>,
+}
+
+impl ApiEndpointConfig {
+    /// The template this endpoint renders through, if any.
+    pub fn template_ref(&self) -> Option<TemplateRef> {
+        self.template
+            .clone()
+            .or_else(|| self.template_num.map(TemplateRef::Num))
+    }
+}
+
+/// What a call_api endpoint's `extract` selects from the upstream JSON.
+///
+/// Either a single path: `"hourly.temperature_2m[0]"`,
+/// or output names mapped to paths: `{"temp": "current.temperature_2m", "times": "hourly.time"}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ExtractConfig {
+    Path(String),
+    Fields(HashMap<String, String>),
+}
+
+impl ExtractConfig {
+    pub fn apply(&self, data: &serde_json::Value) -> Result<serde_json::Value, String> {
+        match self {
+            ExtractConfig::Path(path) => json_extract::select(data, path),
+            ExtractConfig::Fields(fields) => json_extract::extract(data, fields),
+        }
+    }
+}
+
+/// One entry of a get_logs route's `log_file_types`.
+///
+/// Either a bare path, an object giving the log a display name:
+/// `{"name": "Backup", "path": "~/backup.log"}`,
+/// or a systemd journal source:
+/// `{"name": "nginx", "journal": {"unit": "nginx.service", "priority": "warning"}}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum LogFileEntry {
+    Path(String),
+    Named {
+        name: String,
+        path: String,
+    },
+    Journal {
+        name: Option<String>,
+        journal: JournalSource,
+    },
+}
+
+/// Which journal entries a journal log source shows.
+/// Each field is passed to journalctl as is.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JournalSource {
+    /// unit- only entries for this systemd unit, e.g. "nginx.service".
+    pub unit: Option<String>,
+    /// priority- a priority or range, e.g. "warning" or "0..4".
+    pub priority: Option<String>,
+    /// since- default start of the window, e.g. "-1h" or "today".
+    /// Overridden by the `since` query parameter.
+    pub since: Option<String>,
+}
+
+impl LogFileEntry {
+    /// The path as written in the json route, before `~` expansion.
+    /// None for journal sources.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            LogFileEntry::Path(path) | LogFileEntry::Named { path, .. } => Some(path),
+            LogFileEntry::Journal { .. } => None,
+        }
+    }
+
+    /// Human readable name; the file name (or unit) when no name was given.
+    pub fn name(&self) -> String {
+        match self {
+            LogFileEntry::Named { name, .. }
+            | LogFileEntry::Journal {
+                name: Some(name), ..
+            } => name.clone(),
+            LogFileEntry::Journal { journal, .. } => journal
+                .unit
+                .clone()
+                .unwrap_or_else(|| "journal".to_string()),
+            LogFileEntry::Path(path) => std::path::Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone()),
+        }
+    }
 }
 
 /// Enumeration for the JSON structures associated with each possible RouteFunction
@@ -83,6 +327,18 @@ pub enum RouteFunction {
         /// body- a String to display.
         body: String,
     },
+    #[serde(rename = "markdown_page")]
+    MarkdownPage {
+        /// Markdown page.  The body is markdown, inline or as the name of
+        /// a `.md` file in json_routes, rendered into the template.
+        #[serde(flatten)]
+        meta: RouteMeta,
+        /// body- the markdown to render.
+        body: String,
+        /// toc- put a table of contents above the page.  false by default.
+        #[serde(default)]
+        toc: bool,
+    },
     #[serde(rename = "help_page")]
     HelpPage {
         /// Automatically generated help page
@@ -118,14 +374,15 @@ pub enum RouteFunction {
     },
     #[serde(rename = "get_logs")]
     GetLogs {
-        /// use tail on a specific log file
+        /// Read the last lines of a specific log file
         /// within the log_file_types list.
-        /// Used as/endpoint?log=integerhere
+        /// Used as /endpoint?log=index_or_name&lines=50&offset=0&grep=regex&since=time&format=json
+        /// or /endpoint?log=index_or_name&follow=true to stream new lines as server-sent events.
 
         #[serde(flatten)]
         meta: RouteMeta,
-        /// log_file_types- list of log file paths.
-        log_file_types: Option<Vec<String>>,
+        /// log_file_types- list of log file paths, or {"name", "path"} objects.
+        log_file_types: Option<Vec<LogFileEntry>>,
     },
 
     #[serde(rename = "call_api")]
@@ -138,20 +395,78 @@ pub enum RouteFunction {
         base_url: String,
         /// Mapping from endpoint keyword to path
         endpoints: HashMap<String, ApiEndpointConfig>,
+        /// route_method- the HTTP method this route answers to.  GET by default.
+        /// Parameters are read from the query string, and from a form body.
+        #[serde(default)]
+        route_method: ApiMethod,
+        /// fixture_mode- off, record or replay, overriding `api_fixtures.mode`
+        /// in config.yaml for this route.
+        #[serde(default)]
+        fixture_mode: Option<FixtureMode>,
     },
 }
 
 impl RouteFunction {
+    pub fn meta(&self) -> &RouteMeta {
+        match self {
+            RouteFunction::NormalPage { meta, .. }
+            | RouteFunction::MarkdownPage { meta, .. }
+            | RouteFunction::HelpPage { meta, .. }
+            | RouteFunction::CommandStatus { meta, .. }
+            | RouteFunction::RunCommand { meta, .. }
+            | RouteFunction::GetLogs { meta, .. }
+            | RouteFunction::ApiCaller { meta, .. } => meta,
+        }
+    }
+
+    /// The function_type, as written in the json.
+    pub fn function_type(&self) -> &'static str {
+        match self {
+            RouteFunction::NormalPage { .. } => "normal_page",
+            RouteFunction::MarkdownPage { .. } => "markdown_page",
+            RouteFunction::HelpPage { .. } => "help_page",
+            RouteFunction::CommandStatus { .. } => "command_statuses",
+            RouteFunction::RunCommand { .. } => "run_command",
+            RouteFunction::GetLogs { .. } => "get_logs",
+            RouteFunction::ApiCaller { .. } => "call_api",
+        }
+    }
+
+    /// The template the route's pages use.  Help and command status pages
+    /// always use template 0; call_api routes use their endpoints' templates.
+    pub fn template_ref(&self) -> Option<TemplateRef> {
+        match self {
+            RouteFunction::HelpPage { .. } | RouteFunction::CommandStatus { .. } => {
+                Some(TemplateRef::Num(0))
+            }
+            RouteFunction::ApiCaller { .. } => None,
+            _ => Some(self.meta().template_ref()),
+        }
+    }
+
+    /// Whether the route is linked from the nav bar.  call_api routes
+    /// return JSON by default, so they're left out unless asked for.
+    pub fn in_nav(&self) -> bool {
+        self.meta()
+            .nav
+            .unwrap_or(!matches!(self, RouteFunction::ApiCaller { .. }))
+    }
+
+    /// Build the route.  Its templates are resolved here, so unknown ones
+    /// are reported when routes are loaded.
     pub fn into_route(self, help_text: &str) -> (String, axum::routing::MethodRouter) {
+        let template = self
+            .template_ref()
+            .map(|t| template_or_default(&t, &self.meta().route))
+            .unwrap_or_default();
         match self {
             RouteFunction::NormalPage { meta, body } => {
                 let title = meta.title.clone();
                 let body = body.clone();
-                let template = meta.template_num;
 
                 if meta.auth_level <= 0 {
                     let route = get(move || {
-                        normal_page_template_handler(title.clone(), body.clone(), template)
+                        normal_page_template_handler(title.clone(), body.clone(), template.clone())
                     });
 
                     (meta.route.clone(), route)
@@ -161,7 +476,7 @@ impl RouteFunction {
                             auth_session,
                             title.clone(),
                             body.clone(),
-                            template,
+                            template.clone(),
                         )
                     });
 
@@ -169,22 +484,33 @@ impl RouteFunction {
                 }
             }
 
+            RouteFunction::MarkdownPage { meta, body, toc } => {
+                let title = meta.title.clone();
+                // rendered once, and not passed through Tera, so code
+                // blocks can show template syntax
+                let body = render_markdown(&body, toc);
+
+                let route = get(move || {
+                    normal_page_template_handler(title.clone(), body.clone(), template.clone())
+                });
+
+                (meta.route.clone(), route)
+            }
+
             RouteFunction::HelpPage { meta } => {
                 let title = meta.title.clone();
                 let body = help_text.to_string();
-                let template = 0;
 
                 let route = get(move || {
-                    normal_page_template_handler(title.clone(), body.clone(), template)
+                    normal_page_template_handler(title.clone(), body.clone(), template.clone())
                 });
 
                 (meta.route.clone(), route)
             }
             RouteFunction::CommandStatus { meta } => {
                 let title = meta.title.clone();
-                let template = 0;
 
-                let route = get(move || get_command_statuses(title.clone(), template));
+                let route = get(move || get_command_statuses(title.clone(), template.clone()));
 
                 (meta.route.clone(), route)
             }
@@ -199,7 +525,6 @@ impl RouteFunction {
                 let log = log_file_path.clone();
                 let script = script_file_path.clone();
                 let title = meta.title.clone();
-                let template = meta.template_num;
                 if meta.auth_level <= 0 {
                     let route = get(move || {
                         run_command_handler(
@@ -207,7 +532,7 @@ impl RouteFunction {
                             log.clone(),
                             script.clone(),
                             title.clone(),
-                            template,
+                            template.clone(),
                         )
                     });
 
@@ -220,7 +545,7 @@ impl RouteFunction {
                             log.clone(),
                             script.clone(),
                             title.clone(),
-                            template,
+                            template.clone(),
                         )
                     });
 
@@ -235,8 +560,9 @@ impl RouteFunction {
                 let title = meta.title.clone();
                 let logs = log_file_types.clone();
 
-                let route =
-                    get(move |query| get_logs_handler_wrapped(query, logs.clone(), title.clone()));
+                let route = get(move |query| {
+                    get_logs_handler_wrapped(query, logs.clone(), title.clone(), template.clone())
+                });
 
                 (meta.route.clone(), route)
             }
@@ -245,12 +571,28 @@ impl RouteFunction {
                 meta,
                 base_url,
                 endpoints,
+                route_method,
+                fixture_mode,
             } => {
+                let title = meta.title.clone();
                 let base_url = base_url.clone();
-                let endpoints = endpoints.clone();
+                let mut endpoints = endpoints.clone();
+                for (key, endpoint) in endpoints.iter_mut() {
+                    endpoint.resolved_template = endpoint.template_ref().map(|t| {
+                        template_or_default(&t, &format!("{}?endpoint={}", meta.route, key))
+                    });
+                }
 
-                let route = get(move |query| {
-                    api_caller_wrapped(query, base_url.clone(), endpoints.clone())
+                let route = on(route_method.method_filter(), move |query, headers, body| {
+                    api_caller_wrapped(
+                        query,
+                        headers,
+                        body,
+                        title.clone(),
+                        base_url.clone(),
+                        endpoints.clone(),
+                        fixture_mode,
+                    )
                 });
 
                 (meta.route.clone(), route)
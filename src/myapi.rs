@@ -1,12 +1,23 @@
+pub(crate) mod api_cache;
 pub(crate) mod api_call_system;
+pub(crate) mod api_fixtures;
+pub(crate) mod api_params;
 pub(crate) mod handlers;
+pub(crate) mod journal;
+pub(crate) mod json_extract;
+pub(crate) mod log_download;
+pub(crate) mod log_files;
+pub(crate) mod markdown;
 pub(crate) mod shell_script_run;
 
+use crate::htmlv::{NavLink, init_nav};
+use crate::metrics::metrics_handler;
 use crate::my_api_config::RouteFunction;
-use crate::procmon::system_usage_handler;
+use crate::procmon::{
+    alerts::alerts_handler, history::history_handler, system_usage_handler, system_usage_stream,
+};
 
 use axum::{Router, routing::get};
-use html_escape;
 use std::collections::HashMap;
 use tower_http::services::ServeDir;
 
@@ -15,8 +26,6 @@ use std::fs;
 
 use std::path::PathBuf;
 
-use tracing;
-
 /// Get all JSON files from dir_path and load them
 /// valid RouteFunction structs
 ///
@@ -25,19 +34,27 @@ pub fn load_routes_from_dir(dir_path: &str) -> Vec<RouteFunction> {
 
     tracing::info!("Scanning directory: {dir_path}");
 
-    // load all .html files into a HashMap, and keep full file name as key
+    // load all .html and .md files into HashMaps, and keep full file name as key
     let mut html_map = HashMap::new();
+    let mut md_map = HashMap::new();
     if let Ok(entries) = fs::read_dir(dir_path) {
         for entry in entries.flatten() {
             let path = entry.path();
 
-            if path.extension().map_or(false, |ext| ext == "html") {
-                if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                    let content = fs::read_to_string(&path)
-                        .unwrap_or_else(|_| panic!("Failed to read HTML file: {:?}", path));
-                    tracing::info!("Loaded HTML file: {}", filename);
-                    html_map.insert(filename.to_string(), content);
-                }
+            if path.extension().is_some_and(|ext| ext == "html")
+                && let Some(filename) = path.file_name().and_then(|s| s.to_str())
+            {
+                let content = fs::read_to_string(&path)
+                    .unwrap_or_else(|_| panic!("Failed to read HTML file: {:?}", path));
+                tracing::info!("Loaded HTML file: {}", filename);
+                html_map.insert(filename.to_string(), content);
+            } else if path.extension().is_some_and(|ext| ext == "md")
+                && let Some(filename) = path.file_name().and_then(|s| s.to_str())
+            {
+                let content = fs::read_to_string(&path)
+                    .unwrap_or_else(|_| panic!("Failed to read markdown file: {:?}", path));
+                tracing::info!("Loaded markdown file: {}", filename);
+                md_map.insert(filename.to_string(), content);
             }
         }
     } else {
@@ -77,11 +94,20 @@ pub fn load_routes_from_dir(dir_path: &str) -> Vec<RouteFunction> {
 
         // substitute body field with html file contents, if the value in "body" matches
         for mut entry in route_entries {
-            if let Some(body_key) = entry.get("body").and_then(|b| b.as_str()) {
-                if let Some(body_content) = html_map.get(body_key) {
-                    tracing::info!("Replacing 'body' with content from file: {}", body_key);
-                    entry["body"] = Value::String(body_content.clone());
+            if let Some(body_key) = entry.get("body").and_then(|b| b.as_str())
+                && let Some(body_content) = html_map.get(body_key)
+            {
+                tracing::info!("Replacing 'body' with content from file: {}", body_key);
+                entry["body"] = Value::String(body_content.clone());
+            } else if let Some(body_key) = entry.get("body").and_then(|b| b.as_str())
+                && let Some(body_content) = md_map.get(body_key)
+            {
+                // a normal_page with a .md body is rendered as markdown
+                tracing::info!("Replacing 'body' with markdown from file: {}", body_key);
+                if entry.get("function_type").and_then(|f| f.as_str()) == Some("normal_page") {
+                    entry["function_type"] = Value::String("markdown_page".to_string());
                 }
+                entry["body"] = Value::String(body_content.clone());
             }
 
             //  deserialize into a RouteFunction
@@ -92,29 +118,12 @@ pub fn load_routes_from_dir(dir_path: &str) -> Vec<RouteFunction> {
         }
     }
     // sort by help order
-    all_routes.sort_by_key(|route_func| {
-        let meta = match route_func {
-            RouteFunction::NormalPage { meta, .. }
-            | RouteFunction::HelpPage { meta, .. }
-            | RouteFunction::CommandStatus { meta, .. }
-            | RouteFunction::RunCommand { meta, .. }
-            | RouteFunction::GetLogs { meta, .. }
-            | RouteFunction::ApiCaller { meta, .. } => meta,
-        };
-        meta.help_order
-    });
+    all_routes.sort_by_key(|route_func| route_func.meta().help_order);
 
     // group
     let mut grouped: HashMap<String, Vec<RouteFunction>> = HashMap::new();
     for route in all_routes {
-        let group_name = match &route {
-            RouteFunction::NormalPage { meta, .. }
-            | RouteFunction::HelpPage { meta, .. }
-            | RouteFunction::CommandStatus { meta, .. }
-            | RouteFunction::RunCommand { meta, .. }
-            | RouteFunction::GetLogs { meta, .. }
-            | RouteFunction::ApiCaller { meta, .. } => &meta.help_group,
-        };
+        let group_name = &route.meta().help_group;
         grouped.entry(group_name.clone()).or_default().push(route);
     }
 
@@ -138,15 +147,8 @@ pub fn build_help_page_html(route_functions: Vec<RouteFunction>) -> String {
     let mut html = String::from("<h3>Help Page</h3>\n<ul>\n");
 
     let mut current_group: Option<String> = None;
-    for route_func in route_functions {
-        let meta = match route_func {
-            RouteFunction::NormalPage { meta, .. }
-            | RouteFunction::HelpPage { meta, .. }
-            | RouteFunction::CommandStatus { meta, .. }
-            | RouteFunction::RunCommand { meta, .. }
-            | RouteFunction::GetLogs { meta, .. }
-            | RouteFunction::ApiCaller { meta, .. } => meta,
-        };
+    for route_func in &route_functions {
+        let meta = route_func.meta();
 
         // Group handling.
         if current_group.as_ref() != Some(&meta.help_group) {
@@ -179,24 +181,38 @@ pub fn build_help_page_html(route_functions: Vec<RouteFunction>) -> String {
     html
 }
 
+/// Build the nav bar links, in help page order.
+pub fn build_nav_links(route_functions: &[RouteFunction]) -> Vec<NavLink> {
+    route_functions
+        .iter()
+        .filter(|route_func| route_func.in_nav())
+        .map(|route_func| {
+            let meta = route_func.meta();
+            let protected = meta.auth_level >= 1;
+            NavLink {
+                title: meta.title.clone(),
+                href: if protected {
+                    format!("/protected{}", meta.route)
+                } else {
+                    meta.route.clone()
+                },
+                protected,
+            }
+        })
+        .collect()
+}
+
 /// Given a Router and a RouteFunction, add it to the router.
 /// Must pass in the help_text for the /help page.
 pub fn add_route_to_router(router: Router, route_func: RouteFunction, help_text: &str) -> Router {
-    let meta = match &route_func {
-        RouteFunction::NormalPage { meta, .. }
-        | RouteFunction::HelpPage { meta, .. }
-        | RouteFunction::CommandStatus { meta, .. }
-        | RouteFunction::RunCommand { meta, .. }
-        | RouteFunction::GetLogs { meta, .. }
-        | RouteFunction::ApiCaller { meta, .. } => meta,
-    };
+    let meta = route_func.meta();
 
     tracing::info!(
         "{},{},{},{}",
         meta.route,
         meta.title,
         meta.description,
-        meta.template_num
+        meta.template_ref()
     );
     let (path, route) = route_func.into_route(help_text);
     router.route(&path, route)
@@ -207,14 +223,7 @@ pub fn build_router_from_route_functions(route_functions: Vec<RouteFunction>, pr
     let help_text = build_help_page_html(route_functions.clone());
 
     for route_func in route_functions {
-        let meta = match &route_func {
-            RouteFunction::NormalPage { meta, .. }
-            | RouteFunction::HelpPage { meta, .. }
-            | RouteFunction::CommandStatus { meta, .. }
-            | RouteFunction::RunCommand { meta, .. }
-            | RouteFunction::GetLogs { meta, .. }
-            | RouteFunction::ApiCaller { meta, .. } => meta,
-        };
+        let meta = route_func.meta();
 
         if prot == 0 && meta.auth_level <= 0 {
             router = add_route_to_router(router, route_func, &help_text);
@@ -226,9 +235,19 @@ pub fn build_router_from_route_functions(route_functions: Vec<RouteFunction>, pr
 
 pub fn routes() -> Router {
     let route_functions = load_routes_from_dir("./json_routes");
+    init_nav(build_nav_links(&route_functions));
     build_router_from_route_functions(route_functions, 0)
         // MANUAL ROUTES.
         // Extension 1, System Resource Monitor.
         .route("/procmon", get(system_usage_handler))
-        .nest_service("/static", ServeDir::new("statics"))
+        .route("/procmon/stream", get(system_usage_stream))
+        .route("/procmon/history", get(history_handler))
+        .route("/procmon/alerts", get(alerts_handler))
+        // Extension 2, Prometheus metrics.
+        .route("/metrics", get(metrics_handler))
+        // a route rather than nest_service, so requests get a MatchedPath
+        .nest(
+            "/static",
+            Router::new().route_service("/{*path}", ServeDir::new("statics")),
+        )
 }
@@ -0,0 +1,240 @@
+/// Response cache for call_api endpoints with `cache_ttl_secs`.
+/// Entries are keyed on the full upstream request and honour the
+/// upstream `Cache-Control` and `ETag` headers.
+use once_cell::sync::Lazy;
+use reqwest::{RequestBuilder, header};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::myapi::api_call_system::{FetchError, FetchOptions, Fetched, fetch_json};
+
+/// Entries kept before the oldest are dropped.
+const MAX_ENTRIES: usize = 512;
+
+static CACHE: Lazy<Mutex<HashMap<String, CacheEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct CacheEntry {
+    data: Value,
+    etag: Option<String>,
+    fetched_at: Instant,
+    /// How long the entry is fresh: the configured TTL, capped by the
+    /// upstream's max-age.
+    ttl: Duration,
+    /// A background refresh is running for a stale entry.
+    refreshing: bool,
+}
+
+/// Caching settings for one endpoint.
+#[derive(Clone, Copy)]
+pub struct CachePolicy {
+    pub ttl: Duration,
+    /// How long past its TTL an entry may still be served while it is
+    /// refreshed in the background.
+    pub stale_while_revalidate: Duration,
+}
+
+/// How a response was produced, reported in the X-Cache header.
+#[derive(Debug, Clone, Copy)]
+pub enum CacheStatus {
+    Hit,
+    Stale,
+    Revalidated,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Stale => "STALE",
+            CacheStatus::Revalidated => "REVALIDATED",
+            CacheStatus::Miss => "MISS",
+        }
+    }
+}
+
+/// A response from the cache or the upstream.
+pub struct CachedResponse {
+    pub data: Value,
+    pub status: CacheStatus,
+    /// Seconds the data stays fresh, for our own Cache-Control header.
+    pub max_age: u64,
+}
+
+enum Lookup {
+    Fresh(Value, Duration),
+    /// Stale but servable; true if this caller should start the refresh.
+    Stale(Value, bool),
+    /// Too old to serve, but may be revalidated with its ETag.
+    Expired(Option<String>),
+    Miss,
+}
+
+fn lookup(key: &str, policy: CachePolicy) -> Lookup {
+    let mut cache = CACHE.lock().unwrap();
+    let Some(entry) = cache.get_mut(key) else {
+        return Lookup::Miss;
+    };
+    let age = entry.fetched_at.elapsed();
+    if age < entry.ttl {
+        Lookup::Fresh(entry.data.clone(), entry.ttl - age)
+    } else if age < entry.ttl + policy.stale_while_revalidate {
+        let start_refresh = !entry.refreshing;
+        entry.refreshing = true;
+        Lookup::Stale(entry.data.clone(), start_refresh)
+    } else {
+        Lookup::Expired(entry.etag.clone())
+    }
+}
+
+/// Store a fetched response, if the upstream allows it.  Returns the TTL used.
+fn store(key: &str, fetched: &Fetched, data: &Value, policy: CachePolicy) -> Duration {
+    let ttl = match fetched.max_age {
+        Some(max_age) => policy.ttl.min(Duration::from_secs(max_age)),
+        None => policy.ttl,
+    };
+    let mut cache = CACHE.lock().unwrap();
+    if fetched.no_store {
+        cache.remove(key);
+        return Duration::ZERO;
+    }
+    if cache.len() >= MAX_ENTRIES && !cache.contains_key(key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched_at)
+            .map(|(k, _)| k.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(
+        key.to_string(),
+        CacheEntry {
+            data: data.clone(),
+            etag: fetched.etag.clone(),
+            fetched_at: Instant::now(),
+            ttl,
+            refreshing: false,
+        },
+    );
+    ttl
+}
+
+/// Mark an entry fresh again after a 304.  Returns its data and TTL.
+fn revalidated(key: &str, fetched: &Fetched, policy: CachePolicy) -> Option<(Value, Duration)> {
+    let mut cache = CACHE.lock().unwrap();
+    let entry = cache.get_mut(key)?;
+    entry.fetched_at = Instant::now();
+    entry.refreshing = false;
+    entry.ttl = match fetched.max_age {
+        Some(max_age) => policy.ttl.min(Duration::from_secs(max_age)),
+        None => policy.ttl,
+    };
+    if fetched.etag.is_some() {
+        entry.etag = fetched.etag.clone();
+    }
+    Some((entry.data.clone(), entry.ttl))
+}
+
+fn refresh_failed(key: &str) {
+    if let Some(entry) = CACHE.lock().unwrap().get_mut(key) {
+        entry.refreshing = false;
+    }
+}
+
+/// Fetch with a conditional request if we hold an ETag, and update the cache.
+async fn fetch_and_store(
+    mut request: RequestBuilder,
+    key: &str,
+    etag: Option<String>,
+    policy: CachePolicy,
+    url: &str,
+    options: FetchOptions,
+) -> Result<CachedResponse, FetchError> {
+    // kept to refetch unconditionally if the entry is evicted meanwhile
+    let unconditional = request.try_clone();
+    if let Some(etag) = &etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    let fetched = match fetch_json(request, url, options).await {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            refresh_failed(key);
+            return Err(e);
+        }
+    };
+    match &fetched.data {
+        None => {
+            // 304 Not Modified
+            match revalidated(key, &fetched, policy) {
+                Some((data, ttl)) => Ok(CachedResponse {
+                    data,
+                    status: CacheStatus::Revalidated,
+                    max_age: ttl.as_secs(),
+                }),
+                None => match unconditional {
+                    Some(request) => {
+                        Box::pin(fetch_and_store(request, key, None, policy, url, options)).await
+                    }
+                    None => Err(FetchError::Unreachable {
+                        timed_out: false,
+                        message: "Cached response was evicted during revalidation".to_string(),
+                    }),
+                },
+            }
+        }
+        Some(data) => {
+            let ttl = store(key, &fetched, data, policy);
+            Ok(CachedResponse {
+                data: data.clone(),
+                status: CacheStatus::Miss,
+                max_age: ttl.as_secs(),
+            })
+        }
+    }
+}
+
+/// Serve from the cache, refreshing in the background if stale, or fetch.
+/// `request` must be cloneable (no streaming body).
+pub async fn cached_fetch(
+    request: RequestBuilder,
+    key: String,
+    policy: CachePolicy,
+    url: &str,
+    options: FetchOptions,
+) -> Result<CachedResponse, FetchError> {
+    match lookup(&key, policy) {
+        Lookup::Fresh(data, remaining) => Ok(CachedResponse {
+            data,
+            status: CacheStatus::Hit,
+            max_age: remaining.as_secs(),
+        }),
+        Lookup::Stale(data, start_refresh) => {
+            if start_refresh {
+                let etag = CACHE
+                    .lock()
+                    .unwrap()
+                    .get(&key)
+                    .and_then(|entry| entry.etag.clone());
+                let url = url.to_string();
+                tokio::spawn(async move {
+                    if fetch_and_store(request, &key, etag, policy, &url, options)
+                        .await
+                        .is_err()
+                    {
+                        tracing::warn!("Background refresh of cached API response failed");
+                    }
+                });
+            }
+            Ok(CachedResponse {
+                data,
+                status: CacheStatus::Stale,
+                max_age: 0,
+            })
+        }
+        Lookup::Expired(etag) => fetch_and_store(request, &key, etag, policy, url, options).await,
+        Lookup::Miss => fetch_and_store(request, &key, None, policy, url, options).await,
+    }
+}
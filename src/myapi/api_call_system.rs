@@ -1,55 +1,544 @@
 /// A generic external API handler.
-/// Currently only usable for GET requests.
-use axum::{extract::Query, response::IntoResponse, response::Json};
+/// Calls GET, POST, PUT, PATCH or DELETE endpoints, with optional
+/// templated headers and JSON bodies filled from the request parameters.
+use axum::{
+    body::Bytes,
+    extract::Query,
+    http::HeaderMap,
+    response::{IntoResponse, Json, Response},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use once_cell::sync::Lazy;
 use reqwest::{Client, header};
 
 use axum::http::StatusCode;
-use serde_json::Value;
-use serde_urlencoded;
-use std::collections::HashMap;
-use tracing;
-
-use crate::my_api_config::ApiEndpointConfig;
-
-//
-// --- Constant Base URL ---
-
-/// Fetches and returns JSON as a raw `serde_json::Value`.
-async fn fetch_raw_json(client: &Client, url: &str) -> Option<Value> {
-    match client
-        .get(url)
-        .header(header::CONTENT_TYPE, "application/json")
-        .header(header::ACCEPT, "application/json")
-        .header("Accept-Language", "en-US")
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tera::{Context, Tera};
+
+use crate::config::FixtureMode;
+use crate::htmlv::{self, HtmlV};
+use crate::metrics;
+use crate::my_api_config::{ApiEndpointConfig, ApiMethod, NonJsonMode, UpstreamErrorMode};
+use crate::myapi::api_cache::{self, CachePolicy, CacheStatus};
+use crate::myapi::api_fixtures::{self, FixtureKey};
+use crate::myapi::api_params;
+use crate::secrets;
+
+/// Clients by connect timeout, shared by all call_api routes so
+/// connections are pooled.
+static CLIENTS: Lazy<Mutex<HashMap<u64, Client>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The shared client for a connect timeout.
+fn client(connect_timeout_secs: u64) -> Client {
+    CLIENTS
+        .lock()
+        .unwrap()
+        .entry(connect_timeout_secs)
+        .or_insert_with(|| {
+            Client::builder()
+                .connect_timeout(Duration::from_secs(connect_timeout_secs))
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// How to fetch from an endpoint's upstream.
+#[derive(Clone, Copy)]
+pub struct FetchOptions {
+    /// Extra attempts after a retryable failure.  0 for non-idempotent methods.
+    pub retries: u32,
+    pub retry_backoff: Duration,
+    pub non_json: NonJsonMode,
+}
+
+/// A JSON response from the upstream, with its caching headers.
+pub struct Fetched {
+    /// None for 304 Not Modified.
+    pub data: Option<Value>,
+    pub etag: Option<String>,
+    /// max-age from Cache-Control, 0 for no-cache.
+    pub max_age: Option<u64>,
+    /// Cache-Control: no-store.
+    pub no_store: bool,
+}
+
+/// An upstream response that can't be used as JSON data.
+pub enum FetchError {
+    /// The upstream could not be reached, or did not answer in time.
+    Unreachable { timed_out: bool, message: String },
+    /// The upstream answered with an error status.
+    Status {
+        status: StatusCode,
+        content_type: Option<String>,
+        body: Bytes,
+    },
+    /// A successful response that is not JSON, to be proxied as is.
+    NotJson {
+        status: StatusCode,
+        content_type: Option<String>,
+        body: Bytes,
+    },
+}
+
+impl FetchError {
+    /// The response for our client.
+    pub fn into_response(self, mode: UpstreamErrorMode) -> Response {
+        match self {
+            FetchError::Unreachable { timed_out, message } => {
+                let status = if timed_out {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::BAD_GATEWAY
+                };
+                error_json(status, &message, None)
+            }
+            FetchError::Status {
+                status,
+                content_type,
+                body,
+            } => match mode {
+                UpstreamErrorMode::Passthrough => raw_response(status, content_type, body),
+                UpstreamErrorMode::Wrap => {
+                    let text = secrets::redact(&String::from_utf8_lossy(&body));
+                    let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+                    error_json(
+                        StatusCode::BAD_GATEWAY,
+                        &format!("Upstream returned {}", status),
+                        Some((status, body)),
+                    )
+                }
+            },
+            FetchError::NotJson {
+                status,
+                content_type,
+                body,
+            } => raw_response(status, content_type, body),
+        }
+    }
+}
+
+/// A structured error: `{"error": {"status", "message", "upstream_status", "upstream_body"}}`.
+fn error_json(
+    status: StatusCode,
+    message: &str,
+    upstream: Option<(StatusCode, Value)>,
+) -> Response {
+    let mut error = Map::new();
+    error.insert("status".to_string(), status.as_u16().into());
+    error.insert("message".to_string(), message.into());
+    if let Some((upstream_status, upstream_body)) = upstream {
+        error.insert(
+            "upstream_status".to_string(),
+            upstream_status.as_u16().into(),
+        );
+        error.insert("upstream_body".to_string(), upstream_body);
+    }
+    (status, Json(serde_json::json!({ "error": error }))).into_response()
+}
+
+/// An upstream response, passed on with its status and content type.
+fn raw_response(status: StatusCode, content_type: Option<String>, body: Bytes) -> Response {
+    let mut response = (status, body).into_response();
+    if let Some(value) = content_type.and_then(|ct| header::HeaderValue::from_str(&ct).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+/// Read max-age and no-store from a Cache-Control header.
+fn parse_cache_control(value: &str) -> (Option<u64>, bool) {
+    let mut max_age = None;
+    let mut no_store = false;
+    for directive in value.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+        if directive == "no-store" {
+            no_store = true;
+        } else if directive == "no-cache" {
+            max_age = Some(0);
+        } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+            max_age = max_age.or(seconds.trim_matches('"').parse().ok());
+        }
+    }
+    (max_age, no_store)
+}
+
+/// An upstream response, before it is read as JSON.
+pub struct RawResponse {
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub cache_control: Option<String>,
+    pub body: Bytes,
+}
+
+impl RawResponse {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self.status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+}
+
+/// Sends the request once, recording its latency and any failure in
+/// the upstream metrics.
+async fn fetch_raw_once(
+    request: reqwest::RequestBuilder,
+    url: &str,
+) -> Result<RawResponse, FetchError> {
+    let started = Instant::now();
+    let result = send_once(request, url).await;
+    let error = match &result {
+        Ok(raw) if raw.status.is_client_error() || raw.status.is_server_error() => Some("status"),
+        Ok(_) => None,
+        Err(FetchError::Unreachable {
+            timed_out: true, ..
+        }) => Some("timeout"),
+        Err(_) => Some("unreachable"),
+    };
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string());
+    metrics::observe_upstream(&host, started.elapsed().as_secs_f64(), error);
+    result
+}
+
+/// Sends the request and reads the whole response.
+async fn send_once(request: reqwest::RequestBuilder, url: &str) -> Result<RawResponse, FetchError> {
+    let unreachable = |e: reqwest::Error, message: &str| {
+        tracing::error!(
+            "Error fetching {}: {}",
+            secrets::redact(url),
+            secrets::redact(&e.to_string())
+        );
+        FetchError::Unreachable {
+            timed_out: e.is_timeout(),
+            message: if e.is_timeout() {
+                "Timed out calling the remote API".to_string()
+            } else {
+                message.to_string()
+            },
+        }
+    };
+    let resp = request
         .send()
         .await
-    {
-        Ok(resp) => {
-            let body = resp.text().await.ok()?;
-            tracing::debug!("Body content is {}", body);
-            match serde_json::from_str::<Value>(&body) {
-                Ok(data) => Some(data),
-                Err(e) => {
-                    tracing::error!("Error parsing raw JSON from {}: {}", url, e);
-                    None
+        .map_err(|e| unreachable(e, "Failed to reach the remote API"))?;
+
+    let header_str = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v: &header::HeaderValue| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let content_type = header_str(header::CONTENT_TYPE);
+    let etag = header_str(header::ETAG);
+    let cache_control = header_str(header::CACHE_CONTROL);
+    let status = resp.status();
+    let body = resp
+        .bytes()
+        .await
+        .map_err(|e| unreachable(e, "Failed to read the remote API's response"))?;
+    tracing::debug!(
+        "Body content is {}",
+        secrets::redact(&String::from_utf8_lossy(&body))
+    );
+    Ok(RawResponse {
+        status,
+        content_type,
+        etag,
+        cache_control,
+        body,
+    })
+}
+
+/// Sends the request, retrying failures and 429/502/503/504 with
+/// exponential backoff, if the request can be cloned.
+pub async fn fetch_raw(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    options: FetchOptions,
+) -> Result<RawResponse, FetchError> {
+    let mut backoff = options.retry_backoff;
+    let mut attempt = 0;
+    loop {
+        let retry = (attempt < options.retries)
+            .then(|| request.try_clone())
+            .flatten();
+        let Some(retry) = retry else {
+            return fetch_raw_once(request, url).await;
+        };
+        let result = fetch_raw_once(retry, url).await;
+        if result.as_ref().is_ok_and(|raw| !raw.is_retryable()) {
+            return result;
+        }
+        attempt += 1;
+        tracing::warn!(
+            "Retrying {} in {:?} (attempt {} of {})",
+            secrets::redact(url),
+            backoff,
+            attempt,
+            options.retries
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Reads an upstream response as JSON.  An empty body is `null`.
+pub fn parse_response(
+    raw: RawResponse,
+    url: &str,
+    non_json: NonJsonMode,
+) -> Result<Fetched, FetchError> {
+    let (max_age, no_store) = raw
+        .cache_control
+        .as_deref()
+        .map(parse_cache_control)
+        .unwrap_or_default();
+    let mut fetched = Fetched {
+        data: None,
+        etag: raw.etag,
+        max_age,
+        no_store,
+    };
+    if raw.status == StatusCode::NOT_MODIFIED {
+        return Ok(fetched);
+    }
+    if !raw.status.is_success() {
+        tracing::warn!("{} returned {}", secrets::redact(url), raw.status);
+        return Err(FetchError::Status {
+            status: raw.status,
+            content_type: raw.content_type,
+            body: raw.body,
+        });
+    }
+    if raw.body.trim_ascii().is_empty() {
+        fetched.data = Some(Value::Null);
+        return Ok(fetched);
+    }
+    match serde_json::from_slice::<Value>(&raw.body) {
+        Ok(data) => {
+            fetched.data = Some(data);
+            Ok(fetched)
+        }
+        Err(e) => {
+            tracing::debug!("Response from {} is not JSON: {}", secrets::redact(url), e);
+            match non_json {
+                NonJsonMode::Proxy => Err(FetchError::NotJson {
+                    status: raw.status,
+                    content_type: raw.content_type,
+                    body: raw.body,
+                }),
+                NonJsonMode::Base64 => {
+                    fetched.data = Some(serde_json::json!({
+                        "content_type": raw.content_type,
+                        "base64": BASE64_STANDARD.encode(&raw.body),
+                    }));
+                    Ok(fetched)
                 }
             }
         }
-        Err(e) => {
-            tracing::error!("Error fetching {}: {}", url, e);
-            None
+    }
+}
+
+/// Sends the request and returns the JSON response as a raw `serde_json::Value`.
+pub async fn fetch_json(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    options: FetchOptions,
+) -> Result<Fetched, FetchError> {
+    parse_response(
+        fetch_raw(request, url, options).await?,
+        url,
+        options.non_json,
+    )
+}
+
+/// Fill `{{ param }}` placeholders in `template`.
+fn render_template(template: &str, context: &Context) -> Result<String, String> {
+    if !template.contains("{{") && !template.contains("{%") {
+        return Ok(template.to_string());
+    }
+    Tera::one_off(template, context, false).map_err(|e| {
+        // Tera puts the useful part ("Variable `x` not found") in the source.
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(inner) = source {
+            message = inner.to_string();
+            source = inner.source();
+        }
+        format!("'{}': {}", template, message)
+    })
+}
+
+/// The parameter name if `template` is nothing but one `{{ param }}`.
+fn whole_placeholder(template: &str) -> Option<&str> {
+    let name = template
+        .trim()
+        .strip_prefix("{{")?
+        .strip_suffix("}}")?
+        .trim();
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        .then_some(name)
+}
+
+/// How ${secret:NAME} references are filled in: `secrets::resolve`, or
+/// `secrets::resolve_or_placeholder` when nothing is sent upstream.
+type SecretResolver = fn(&str) -> Result<String, String>;
+
+/// Fill the JSON body template.  A string that is only a placeholder is
+/// replaced by the parameter parsed as JSON (so "true" or "42" keep their
+/// type), falling back to the plain string.
+fn render_body(
+    template: &Value,
+    params: &BTreeMap<String, String>,
+    context: &Context,
+    resolve: SecretResolver,
+) -> Result<Value, String> {
+    match template {
+        Value::String(text) => {
+            let text = &resolve(text)?;
+            if let Some(value) = whole_placeholder(text).and_then(|name| params.get(name)) {
+                return Ok(
+                    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()))
+                );
+            }
+            render_template(text, context).map(Value::String)
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render_body(item, params, context, resolve))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), render_body(value, params, context, resolve)?)))
+            .collect::<Result<Map<_, _>, String>>()
+            .map(Value::Object),
+        other => Ok(other.clone()),
+    }
+}
+
+/// The default headers, overridden or extended by the configured ones.
+fn build_headers(
+    configured: &HashMap<String, String>,
+    context: &Context,
+    resolve: SecretResolver,
+) -> Result<header::HeaderMap, String> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        header::ACCEPT,
+        header::HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        header::ACCEPT_LANGUAGE,
+        header::HeaderValue::from_static("en-US"),
+    );
+    for (name, value) in configured {
+        let value = resolve(value)
+            .and_then(|value| render_template(&value, context))
+            .map_err(|e| format!("Invalid header {}: {}", name, e))?;
+        let name = header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("Invalid header name {}: {}", name, e))?;
+        let value = header::HeaderValue::from_str(&value)
+            .map_err(|e| format!("Invalid value for header {}: {}", name, e))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+/// Parameters from a urlencoded form body, if the request has one.
+fn form_params(headers: &HeaderMap, body: &Bytes) -> HashMap<String, String> {
+    let is_form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if is_form && !body.is_empty() {
+        serde_urlencoded::from_bytes(body).unwrap_or_default()
+    } else {
+        HashMap::new()
+    }
+}
+
+/// Our own ETag for a response body.
+fn etag_for(data: &Value) -> String {
+    let mut hasher = DefaultHasher::new();
+    data.to_string().hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// The JSON response, or 304 if the client already has it.
+fn json_response(
+    data: Value,
+    request_headers: &HeaderMap,
+    cache: Option<(CacheStatus, u64)>,
+) -> Response {
+    let etag = etag_for(&data);
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Json(data).into_response()
+    };
+    if let Ok(etag) = header::HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    add_cache_headers(&mut response, cache);
+    response
+}
+
+fn add_cache_headers(response: &mut Response, cache: Option<(CacheStatus, u64)>) {
+    if let Some((status, max_age)) = cache {
+        let headers = response.headers_mut();
+        headers.insert("x-cache", header::HeaderValue::from_static(status.as_str()));
+        if let Ok(value) = header::HeaderValue::from_str(&format!("max-age={}", max_age)) {
+            headers.insert(header::CACHE_CONTROL, value);
         }
     }
 }
 
+/// Whether the client asked for JSON rather than a page.
+fn wants_json(request_headers: &HeaderMap) -> bool {
+    request_headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json") && !accept.contains("text/html"))
+}
+
 /// Handler for calling an external API with dynamic endpoint and base URL.
 /// Base_URL and endpoints are loaded within the relevant json_routes
 pub async fn api_caller(
-    Query(params): Query<HashMap<String, String>>,
+    Query(mut params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+    title: String,
     base_url: String,
     endpoints: HashMap<String, ApiEndpointConfig>,
+    fixture_mode: Option<FixtureMode>,
 ) -> impl IntoResponse {
-    let client = Client::new();
+    // form fields take precedence over the query string
+    params.extend(form_params(&headers, &body));
 
     // match the endpoint to the path parameter
     let endpoint_key = match params.get("endpoint") {
@@ -63,36 +552,194 @@ pub async fn api_caller(
         None => return (StatusCode::BAD_REQUEST, "Invalid endpoint").into_response(),
     };
 
-    let mut url = format!("{}{}", base_url, endpoint_cfg.path);
-
-    // merge default params and user-supplied ones
-    let mut merged_params = endpoint_cfg.default_params.clone();
+    // ${secret:NAME} references are only resolved in the route config,
+    // never in user-supplied parameters.  Replayed fixtures are keyed on
+    // redacted secrets, so they don't need the secrets to be set.
+    let fixture_mode = api_fixtures::fixture_mode(fixture_mode);
+    let resolve: SecretResolver = if fixture_mode == FixtureMode::Replay {
+        secrets::resolve_or_placeholder
+    } else {
+        secrets::resolve
+    };
+    let resolved = resolve(&format!("{}{}", base_url, endpoint_cfg.path)).and_then(|url| {
+        endpoint_cfg
+            .default_params
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), resolve(v)?)))
+            .collect::<Result<HashMap<_, _>, String>>()
+            .map(|params| (url, params))
+    });
+    let (mut url, merged_params) = match resolved {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            tracing::error!("call_api endpoint {}: {}", endpoint_key, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+    };
 
     for field in &["path", "default_params"] {
         tracing::debug!("Calling path {}: {:?}", field, endpoint_cfg.path);
     }
 
-    for (k, v) in &params {
-        if k != "endpoint" {
-            merged_params.insert(k.clone(), v.clone());
-            tracing::debug!("added key: {} with value: {}", k, v);
+    params.remove("endpoint");
+    let mut locked = BTreeMap::new();
+    if let Some(rules) = &endpoint_cfg.params {
+        if let Err(problems) = api_params::validate(rules, &params, &endpoint_cfg.default_params) {
+            tracing::warn!(
+                "call_api endpoint {}: rejected parameters: {}",
+                endpoint_key,
+                problems.join("; ")
+            );
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": {
+                        "status": 400,
+                        "message": "Invalid parameters",
+                        "problems": problems,
+                    }
+                })),
+            )
+                .into_response();
+        }
+        for (name, value) in api_params::locked_values(rules) {
+            match resolve(value) {
+                Ok(value) => locked.insert(name.to_string(), value),
+                Err(e) => {
+                    tracing::error!("call_api endpoint {}: {}", endpoint_key, e);
+                    return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+                }
+            };
         }
     }
-    tracing::info!("Calling url {}", url);
-    if !merged_params.is_empty() {
+
+    // sorted, so the same parameters always give the same url (and cache key)
+    let mut merged_params: BTreeMap<String, String> = merged_params.into_iter().collect();
+    for (k, v) in &params {
+        merged_params.insert(k.clone(), v.clone());
+        tracing::debug!("added key: {} with value: {}", k, secrets::redact(v));
+    }
+    merged_params.extend(locked);
+    let context = match Context::from_serialize(&merged_params) {
+        Ok(context) => context,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let json_body = match &endpoint_cfg.body {
+        Some(template) => match render_body(template, &merged_params, &context, resolve) {
+            Ok(body) => Some(body),
+            Err(e) => {
+                let e = secrets::redact(&e);
+                return (StatusCode::BAD_REQUEST, format!("Invalid body: {}", e)).into_response();
+            }
+        },
+        None => None,
+    };
+
+    tracing::info!(
+        "Calling {:?} url {}",
+        endpoint_cfg.method,
+        secrets::redact(&url)
+    );
+    // parameters go into the body when there is one, the query string otherwise
+    if json_body.is_none() && !merged_params.is_empty() {
         let query_str =
             serde_urlencoded::to_string(&merged_params).unwrap_or_else(|_| "".to_string());
-        tracing::info!("Encoded query string: {}", query_str);
+        tracing::info!("Encoded query string: {}", secrets::redact(&query_str));
         url.push('?');
         url.push_str(&query_str);
     }
 
-    match fetch_raw_json(&client, &url).await {
-        Some(data) => Json(data).into_response(),
-        None => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to fetch data from remote API",
-        )
-            .into_response(),
+    let request_headers = match build_headers(&endpoint_cfg.headers, &context, resolve) {
+        Ok(request_headers) => request_headers,
+        Err(e) => return (StatusCode::BAD_REQUEST, secrets::redact(&e)).into_response(),
+    };
+    // only GETs are cached; other methods change something upstream
+    let cache = endpoint_cfg
+        .cache_ttl_secs
+        .filter(|ttl| *ttl > 0 && endpoint_cfg.method == ApiMethod::Get)
+        .map(|ttl| CachePolicy {
+            ttl: Duration::from_secs(ttl),
+            stale_while_revalidate: Duration::from_secs(
+                endpoint_cfg.stale_while_revalidate_secs.unwrap_or(0),
+            ),
+        });
+    let cache_key = format!(
+        "{} {} {:?} {}",
+        endpoint_cfg.method.as_reqwest(),
+        url,
+        request_headers,
+        json_body
+            .as_ref()
+            .map(|b| b.to_string())
+            .unwrap_or_default()
+    );
+
+    let mut request = client(endpoint_cfg.connect_timeout_secs)
+        .request(endpoint_cfg.method.as_reqwest(), &url)
+        .timeout(Duration::from_secs(endpoint_cfg.timeout_secs))
+        .headers(request_headers);
+    if let Some(json_body) = &json_body {
+        request = request.json(json_body);
+    }
+    let options = FetchOptions {
+        retries: if endpoint_cfg.method.is_idempotent() {
+            endpoint_cfg.retries
+        } else {
+            0
+        },
+        retry_backoff: Duration::from_millis(endpoint_cfg.retry_backoff_ms),
+        non_json: endpoint_cfg.non_json,
+    };
+
+    // fixtures bypass the cache, so every request is recorded or replayed
+    let response = match cache {
+        _ if fixture_mode != FixtureMode::Off => {
+            let key = FixtureKey::new(
+                &endpoint_key,
+                endpoint_cfg.method.as_reqwest().as_str(),
+                &url,
+                json_body.as_ref(),
+            );
+            api_fixtures::fetch_with_fixtures(fixture_mode, request, &key, &url, options)
+                .await
+                .map(|fetched| (fetched.data.unwrap_or(Value::Null), None))
+        }
+        Some(policy) => api_cache::cached_fetch(request, cache_key, policy, &url, options)
+            .await
+            .map(|cached| (cached.data, Some((cached.status, cached.max_age)))),
+        None => fetch_json(request, &url, options)
+            .await
+            .map(|fetched| (fetched.data.unwrap_or(Value::Null), None)),
+    };
+    let (data, cache) = match response {
+        Ok(response) => response,
+        Err(e) => return e.into_response(endpoint_cfg.upstream_errors),
+    };
+
+    // the cache holds the upstream's response; extract from it each time
+    let data = match &endpoint_cfg.extract {
+        Some(extract) => match extract.apply(&data) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("call_api endpoint {}: bad extract: {}", endpoint_key, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+        },
+        None => data,
+    };
+
+    match &endpoint_cfg.resolved_template {
+        Some(template) if !wants_json(&headers) => {
+            match htmlv::render_data_html(template, &title, &data) {
+                Ok(html) => {
+                    let mut response = HtmlV(html).into_response();
+                    add_cache_headers(&mut response, cache);
+                    response
+                }
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+            }
+        }
+        _ => json_response(data, &headers, cache),
     }
 }
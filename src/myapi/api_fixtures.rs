@@ -0,0 +1,229 @@
+/// Record-and-replay fixtures for call_api upstreams.
+/// In record mode each upstream response is saved as a JSON file keyed by
+/// the request's method, URL (with its parameters) and body.  In replay mode
+/// those files are served instead, so routes work without the network.
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::config::{ApiFixturesConfig, FixtureMode};
+use crate::myapi::api_call_system::{
+    FetchError, FetchOptions, Fetched, RawResponse, fetch_raw, parse_response,
+};
+use crate::secrets;
+
+static FIXTURES: OnceLock<ApiFixturesConfig> = OnceLock::new();
+
+/// Set the global fixture mode.  Should be called once at startup.
+pub fn init_fixtures(config: &ApiFixturesConfig) {
+    if config.mode != FixtureMode::Off {
+        tracing::warn!(
+            "call_api fixtures: {:?} mode, using {}",
+            config.mode,
+            config.dir
+        );
+    }
+    if FIXTURES.set(config.clone()).is_err() {
+        tracing::warn!("Fixtures were already initialized");
+    }
+}
+
+/// The mode for a route: its own `fixture_mode`, or the global one.
+pub fn fixture_mode(route_mode: Option<FixtureMode>) -> FixtureMode {
+    route_mode
+        .or_else(|| FIXTURES.get().map(|config| config.mode))
+        .unwrap_or_default()
+}
+
+/// One saved upstream response.  The body is kept as JSON when it is JSON,
+/// so fixtures can be read and edited by hand.
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_body: Option<String>,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_control: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+}
+
+impl Fixture {
+    fn body(&self) -> Result<Bytes, String> {
+        if let Some(json) = &self.json {
+            return Ok(Bytes::from(json.to_string()));
+        }
+        if let Some(text) = &self.text {
+            return Ok(Bytes::from(text.clone()));
+        }
+        match &self.base64 {
+            Some(encoded) => BASE64_STANDARD
+                .decode(encoded)
+                .map(Bytes::from)
+                .map_err(|e| e.to_string()),
+            None => Ok(Bytes::new()),
+        }
+    }
+}
+
+/// FNV-1a, since fixture names must stay the same across builds.
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Keep only characters safe in a file name.
+fn file_safe(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The request a fixture is keyed on.  Secrets are redacted, so fixtures
+/// neither hold them nor change when they are rotated.
+pub struct FixtureKey {
+    endpoint: String,
+    method: String,
+    url: String,
+    request_body: Option<String>,
+}
+
+impl FixtureKey {
+    pub fn new(endpoint: &str, method: &str, url: &str, request_body: Option<&Value>) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            method: method.to_string(),
+            url: secrets::redact(url),
+            request_body: request_body.map(|body| secrets::redact(&body.to_string())),
+        }
+    }
+
+    /// `<dir>/<host>/<endpoint>-<METHOD>-<hash>.json`
+    fn path(&self) -> PathBuf {
+        let dir = FIXTURES
+            .get()
+            .map(|config| config.dir.clone())
+            .unwrap_or_else(|| "./fixtures".to_string());
+        let host = reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(file_safe))
+            .unwrap_or_else(|| "unknown".to_string());
+        let hash = stable_hash(&format!(
+            "{} {} {}",
+            self.method,
+            self.url,
+            self.request_body.as_deref().unwrap_or_default()
+        ));
+        PathBuf::from(dir).join(host).join(format!(
+            "{}-{}-{:016x}.json",
+            file_safe(&self.endpoint),
+            self.method,
+            hash
+        ))
+    }
+}
+
+fn replay(key: &FixtureKey) -> Result<RawResponse, FetchError> {
+    let path = key.path();
+    let missing = |detail: String| {
+        tracing::warn!(
+            "No usable fixture for {} {}: {}",
+            key.method,
+            key.url,
+            detail
+        );
+        FetchError::Unreachable {
+            timed_out: false,
+            message: format!("No recorded fixture for this request ({})", path.display()),
+        }
+    };
+    let raw = std::fs::read_to_string(&path).map_err(|e| missing(e.to_string()))?;
+    let fixture: Fixture = serde_json::from_str(&raw).map_err(|e| missing(e.to_string()))?;
+    tracing::debug!("Replaying fixture {}", path.display());
+    Ok(RawResponse {
+        status: StatusCode::from_u16(fixture.status).map_err(|e| missing(e.to_string()))?,
+        body: fixture.body().map_err(missing)?,
+        content_type: fixture.content_type,
+        etag: fixture.etag,
+        cache_control: fixture.cache_control,
+    })
+}
+
+fn record(key: &FixtureKey, raw: &RawResponse) -> std::io::Result<PathBuf> {
+    let body = secrets::redact(&String::from_utf8_lossy(&raw.body));
+    let (json, text, base64) = if raw.body.trim_ascii().is_empty() {
+        (None, None, None)
+    } else if let Ok(json) = serde_json::from_str::<Value>(&body) {
+        (Some(json), None, None)
+    } else if std::str::from_utf8(&raw.body).is_ok() {
+        (None, Some(body), None)
+    } else {
+        (None, None, Some(BASE64_STANDARD.encode(&raw.body)))
+    };
+    let fixture = Fixture {
+        method: key.method.clone(),
+        url: key.url.clone(),
+        request_body: key.request_body.clone(),
+        status: raw.status.as_u16(),
+        content_type: raw.content_type.clone(),
+        etag: raw.etag.clone(),
+        cache_control: raw.cache_control.clone(),
+        json,
+        text,
+        base64,
+    };
+    let path = key.path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let contents = serde_json::to_string_pretty(&fixture).map_err(std::io::Error::other)?;
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Fetch through the fixtures: save the upstream's response in record
+/// mode, or serve the saved one in replay mode.
+pub async fn fetch_with_fixtures(
+    mode: FixtureMode,
+    request: RequestBuilder,
+    key: &FixtureKey,
+    url: &str,
+    options: FetchOptions,
+) -> Result<Fetched, FetchError> {
+    let raw = match mode {
+        FixtureMode::Replay => replay(key)?,
+        FixtureMode::Record | FixtureMode::Off => {
+            let raw = fetch_raw(request, url, options).await?;
+            if mode == FixtureMode::Record {
+                match record(key, &raw) {
+                    Ok(path) => tracing::info!("Recorded fixture {}", path.display()),
+                    Err(e) => tracing::error!("Failed to record fixture: {}", e),
+                }
+            }
+            raw
+        }
+    };
+    parse_response(raw, url, options.non_json)
+}
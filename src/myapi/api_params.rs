@@ -0,0 +1,136 @@
+/// Parameter allowlisting for call_api endpoints with `params`.
+/// Only declared parameters are passed upstream, and each is checked
+/// against its rule before the call is made.
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// The type a parameter's value must parse as.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean,
+}
+
+/// The rule for one allowed parameter.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ParamRule {
+    /// type- `string` (default), `integer`, `number` or `boolean`.
+    #[serde(default, rename = "type")]
+    pub kind: ParamType,
+    /// required- reject requests without this parameter (and no default).
+    #[serde(default)]
+    pub required: bool,
+    /// min / max- inclusive range for integers and numbers.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// pattern- a regex the whole value must match.
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    pub pattern: Option<Regex>,
+    /// values- the only values accepted.
+    pub values: Option<Vec<String>>,
+    /// locked- always send this value.  Clients may only repeat it.
+    /// May use ${secret:NAME}.
+    pub locked: Option<String>,
+}
+
+/// Compile a pattern at load time, anchored so it must match the whole value.
+fn deserialize_pattern<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern: Option<String> = Option::deserialize(deserializer)?;
+    pattern
+        .map(|p| Regex::new(&format!("^(?:{})$", p)).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+impl ParamRule {
+    /// Check one supplied value, returning the problem if any.
+    fn check(&self, name: &str, value: &str) -> Option<String> {
+        if let Some(locked) = &self.locked {
+            // compared unresolved, so a secret is never matched or echoed
+            return (value != locked).then(|| format!("'{}' can't be changed", name));
+        }
+        let number = match self.kind {
+            ParamType::String => None,
+            ParamType::Integer => match value.parse::<i64>() {
+                Ok(n) => Some(n as f64),
+                Err(_) => return Some(format!("'{}' must be an integer", name)),
+            },
+            ParamType::Number => match value.parse::<f64>() {
+                Ok(n) if n.is_finite() => Some(n),
+                _ => return Some(format!("'{}' must be a number", name)),
+            },
+            ParamType::Boolean => {
+                if value != "true" && value != "false" {
+                    return Some(format!("'{}' must be true or false", name));
+                }
+                None
+            }
+        };
+        if let Some(n) = number {
+            if let Some(min) = self.min.filter(|min| n < *min) {
+                return Some(format!("'{}' must be at least {}", name, min));
+            }
+            if let Some(max) = self.max.filter(|max| n > *max) {
+                return Some(format!("'{}' must be at most {}", name, max));
+            }
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(value)
+        {
+            return Some(format!("'{}' has an invalid format", name));
+        }
+        if let Some(values) = &self.values
+            && !values.iter().any(|v| v == value)
+        {
+            return Some(format!("'{}' must be one of: {}", name, values.join(", ")));
+        }
+        None
+    }
+}
+
+/// Check the client's parameters against the endpoint's rules.
+///
+/// `params` excludes `endpoint`; `defaults` are the endpoint's
+/// default_params, which satisfy `required`.  Returns every problem found.
+pub fn validate(
+    rules: &HashMap<String, ParamRule>,
+    params: &HashMap<String, String>,
+    defaults: &HashMap<String, String>,
+) -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+    for (name, value) in params {
+        match rules.get(name) {
+            Some(rule) => problems.extend(rule.check(name, value)),
+            None => problems.push(format!("'{}' is not an allowed parameter", name)),
+        }
+    }
+    for (name, rule) in rules {
+        if rule.required
+            && rule.locked.is_none()
+            && !params.contains_key(name)
+            && !defaults.contains_key(name)
+        {
+            problems.push(format!("'{}' is required", name));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        problems.sort();
+        Err(problems)
+    }
+}
+
+/// The locked values, to be sent whatever the client asked for.
+pub fn locked_values(rules: &HashMap<String, ParamRule>) -> impl Iterator<Item = (&str, &str)> {
+    rules
+        .iter()
+        .filter_map(|(name, rule)| Some((name.as_str(), rule.locked.as_deref()?)))
+}
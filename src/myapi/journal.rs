@@ -0,0 +1,201 @@
+/// systemd journal log source for get_logs routes.
+/// Runs `journalctl --output=json` directly (never through a shell) and
+/// reads its output one entry per line.
+use serde_json::{Map, Value};
+use std::io::{self, BufRead, BufReader, Lines, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::io::AsyncBufReadExt;
+
+use crate::my_api_config::JournalSource;
+
+/// One journal entry with all of its fields, as printed by journalctl.
+pub type JournalEntry = Map<String, Value>;
+
+/// syslog priority names, indexed by the PRIORITY field.
+const PRIORITY_NAMES: [&str; 8] = [
+    "EMERG", "ALERT", "CRIT", "ERROR", "WARN", "NOTICE", "INFO", "DEBUG",
+];
+
+fn journal_command(source: &JournalSource, since: Option<OffsetDateTime>) -> Command {
+    let mut command = Command::new("journalctl");
+    command.args(["--output=json", "--no-pager", "--quiet"]);
+    if let Some(unit) = &source.unit {
+        command.arg(format!("--unit={}", unit));
+    }
+    if let Some(priority) = &source.priority {
+        command.arg(format!("--priority={}", priority));
+    }
+    match since {
+        Some(since) => {
+            command.arg(format!("--since=@{}", since.unix_timestamp()));
+        }
+        None => {
+            if let Some(since) = &source.since {
+                command.arg(format!("--since={}", since));
+            }
+        }
+    }
+    command
+}
+
+/// Journal entries, newest first.
+///
+/// journalctl is stopped once the reader is dropped, so only as many
+/// entries as are consumed are ever read.  Reading blocks, so use it from
+/// `spawn_blocking`.
+pub struct JournalReader {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+    /// drains stderr as it is written, so journalctl can't block on a full pipe
+    stderr: Option<JoinHandle<String>>,
+    finished: bool,
+}
+
+impl JournalReader {
+    pub fn open(source: &JournalSource, since: Option<OffsetDateTime>) -> io::Result<Self> {
+        let mut child = journal_command(source, since)
+            .arg("--reverse")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("journalctl has no stdout"))?;
+        let stderr = child.stderr.take().map(|mut pipe| {
+            thread::spawn(move || {
+                let mut stderr = String::new();
+                let _ = pipe.read_to_string(&mut stderr);
+                stderr
+            })
+        });
+        Ok(Self {
+            child,
+            lines: BufReader::new(stdout).lines(),
+            stderr,
+            finished: false,
+        })
+    }
+
+    /// If journalctl ran to the end and failed, its error message.
+    pub fn error(&mut self) -> Option<String> {
+        if !self.finished {
+            return None;
+        }
+        let status = self.child.wait().ok()?;
+        if status.success() {
+            return None;
+        }
+        let stderr = self
+            .stderr
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        Some(format!("journalctl {}: {}", status, stderr.trim()))
+    }
+}
+
+impl Iterator for JournalReader {
+    type Item = JournalEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in self.lines.by_ref() {
+            let Ok(line) = line else { break };
+            match serde_json::from_str(&line) {
+                Ok(entry) => return Some(entry),
+                Err(e) => tracing::debug!("Skipping unparseable journal line: {}", e),
+            }
+        }
+        self.finished = true;
+        None
+    }
+}
+
+impl Drop for JournalReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Follows the journal like `journalctl --follow`, starting with entries
+/// written after it is created.  journalctl is killed when this is dropped.
+pub struct JournalFollower {
+    _child: tokio::process::Child,
+    lines: tokio::io::Lines<tokio::io::BufReader<tokio::process::ChildStdout>>,
+}
+
+impl JournalFollower {
+    pub fn new(source: &JournalSource) -> io::Result<Self> {
+        let mut child = tokio::process::Command::from(journal_command(source, None))
+            .args(["--follow", "--lines=0"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("journalctl has no stdout"))?;
+        Ok(Self {
+            _child: child,
+            lines: tokio::io::BufReader::new(stdout).lines(),
+        })
+    }
+
+    /// Wait for the next entry.  None once journalctl exits.
+    pub async fn next_entry(&mut self) -> Option<JournalEntry> {
+        while let Some(line) = self.lines.next_line().await.ok()? {
+            match serde_json::from_str(&line) {
+                Ok(entry) => return Some(entry),
+                Err(e) => tracing::debug!("Skipping unparseable journal line: {}", e),
+            }
+        }
+        None
+    }
+}
+
+/// A field as text.  journalctl prints fields that are not valid UTF-8
+/// as arrays of bytes.
+pub fn field_text(entry: &JournalEntry, field: &str) -> Option<String> {
+    match entry.get(field)? {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        other => Some(other.to_string()),
+    }
+}
+
+/// The entry's realtime timestamp.
+pub fn entry_time(entry: &JournalEntry) -> Option<OffsetDateTime> {
+    let micros: i128 = field_text(entry, "__REALTIME_TIMESTAMP")?.parse().ok()?;
+    OffsetDateTime::from_unix_timestamp_nanos(micros * 1000).ok()
+}
+
+/// Format an entry as a single log line: `time [PRIORITY] identifier[pid]: message`.
+pub fn format_entry(entry: &JournalEntry) -> String {
+    let time = entry_time(entry)
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_default();
+    let priority = field_text(entry, "PRIORITY")
+        .and_then(|p| p.parse::<usize>().ok())
+        .and_then(|p| PRIORITY_NAMES.get(p).copied())
+        .unwrap_or("-");
+    let identifier = field_text(entry, "SYSLOG_IDENTIFIER")
+        .or_else(|| field_text(entry, "_COMM"))
+        .unwrap_or_else(|| "-".to_string());
+    let pid = field_text(entry, "_PID")
+        .map(|pid| format!("[{}]", pid))
+        .unwrap_or_default();
+    let message = field_text(entry, "MESSAGE").unwrap_or_default();
+    format!("{} [{}] {}{}: {}", time, priority, identifier, pid, message)
+}
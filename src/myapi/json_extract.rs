@@ -0,0 +1,106 @@
+/// JSONPath-style selection for call_api `extract`.
+///
+/// Paths are dotted keys with optional indexes, e.g. `current.temperature_2m`,
+/// `hourly.time[0]`, `hourly.time[-1]`, `items[*].name` or `$['odd key']`.
+/// A leading `$` is optional.  Missing values select `null`.
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    Key(String),
+    /// Negative indexes count from the end.
+    Index(i64),
+    /// Every element of an array, or every value of an object.
+    All,
+}
+
+fn parse_bracket(inner: &str) -> Result<Step, String> {
+    let inner = inner.trim();
+    if inner == "*" {
+        return Ok(Step::All);
+    }
+    for quote in ['\'', '"'] {
+        if let Some(key) = inner
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return Ok(Step::Key(key.to_string()));
+        }
+    }
+    inner
+        .parse()
+        .map(Step::Index)
+        .map_err(|_| format!("Invalid index [{}]", inner))
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let path = path.trim();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after
+                .find(']')
+                .ok_or_else(|| format!("Unclosed '[' in path '{}'", path))?;
+            steps.push(parse_bracket(&after[..end])?);
+            rest = &after[end + 1..];
+        } else {
+            let after = rest.strip_prefix('.').unwrap_or(rest);
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let key = &after[..end];
+            if key.is_empty() {
+                return Err(format!("Empty key in path '{}'", path));
+            }
+            steps.push(if key == "*" {
+                Step::All
+            } else {
+                Step::Key(key.to_string())
+            });
+            rest = &after[end..];
+        }
+    }
+    Ok(steps)
+}
+
+fn apply(value: &Value, steps: &[Step]) -> Value {
+    let Some((step, rest)) = steps.split_first() else {
+        return value.clone();
+    };
+    match (step, value) {
+        (Step::Key(key), Value::Object(map)) => {
+            map.get(key).map_or(Value::Null, |v| apply(v, rest))
+        }
+        (Step::Index(i), Value::Array(items)) => {
+            let index = if *i < 0 {
+                items.len().checked_sub(i.unsigned_abs() as usize)
+            } else {
+                Some(*i as usize)
+            };
+            index
+                .and_then(|index| items.get(index))
+                .map_or(Value::Null, |v| apply(v, rest))
+        }
+        (Step::All, Value::Array(items)) => {
+            Value::Array(items.iter().map(|v| apply(v, rest)).collect())
+        }
+        (Step::All, Value::Object(map)) => {
+            Value::Array(map.values().map(|v| apply(v, rest)).collect())
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Select the value at `path`.
+pub fn select(value: &Value, path: &str) -> Result<Value, String> {
+    Ok(apply(value, &parse_path(path)?))
+}
+
+/// Build an object with each output name set to the value at its path.
+pub fn extract(value: &Value, fields: &HashMap<String, String>) -> Result<Value, String> {
+    let mut output = Map::new();
+    for (name, path) in fields {
+        output.insert(name.clone(), select(value, path)?);
+    }
+    Ok(Value::Object(output))
+}
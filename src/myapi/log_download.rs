@@ -0,0 +1,376 @@
+/// Download endpoint for configured log files.
+/// Serves any file named by a get_logs route or a run_command log_file_path,
+/// plus their rotated archives, and nothing else.
+use axum::{
+    Router,
+    body::Body,
+    extract::{Query, RawQuery, Request},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::logging::is_archive_named;
+use crate::my_api_config::RouteFunction;
+
+/// A file that may be downloaded.
+#[derive(Debug, Clone, Serialize)]
+struct DownloadableLog {
+    /// The path, as passed back in the `file` parameter.
+    path: String,
+    /// The route that allows this file.
+    route: String,
+    size: u64,
+    modified: Option<String>,
+    /// True for rotated archives of a configured log.
+    archive: bool,
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    file: String,
+}
+
+#[derive(Clone, Copy)]
+enum BundleFormat {
+    Zip,
+    TarGz,
+}
+
+/// Configured log paths (after `~` expansion) with the route allowing each.
+fn configured_logs(route_functions: &[RouteFunction]) -> Vec<(PathBuf, String)> {
+    let mut logs = Vec::new();
+    for route_func in route_functions {
+        match route_func {
+            RouteFunction::GetLogs {
+                meta,
+                log_file_types,
+            } => {
+                for path in log_file_types.iter().flatten().filter_map(|log| log.path()) {
+                    logs.push((
+                        PathBuf::from(shellexpand::tilde(path).to_string()),
+                        meta.route.clone(),
+                    ));
+                }
+            }
+            RouteFunction::RunCommand {
+                meta,
+                log_file_path,
+                ..
+            } => logs.push((
+                PathBuf::from(shellexpand::tilde(log_file_path).to_string()),
+                meta.route.clone(),
+            )),
+            _ => {}
+        }
+    }
+    logs
+}
+
+/// Is `candidate` a rotated copy of the log named `file_name`?
+///
+/// Matches logrotate style `app.log.1` / `app.log.2.gz` as well as this
+/// server's own `app.1.log` / `app.20250101T000000.log.gz`.
+fn is_rotated_archive(candidate: &str, file_name: &str) -> bool {
+    if is_archive_named(candidate, file_name, "") {
+        return true;
+    }
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => {
+            is_archive_named(candidate, stem, &format!(".{}", ext))
+        }
+        _ => false,
+    }
+}
+
+fn describe(path: &Path, route: &str, archive: bool) -> Option<DownloadableLog> {
+    let meta = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    Some(DownloadableLog {
+        path: path.to_string_lossy().into_owned(),
+        route: route.to_string(),
+        size: meta.len(),
+        modified: meta
+            .modified()
+            .ok()
+            .and_then(|t| OffsetDateTime::from(t).format(&Rfc3339).ok()),
+        archive,
+    })
+}
+
+/// Every downloadable file that exists right now: the configured logs and
+/// any rotated archives next to them.
+fn downloadable_logs(route_functions: &[RouteFunction]) -> Vec<DownloadableLog> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for (path, route) in configured_logs(route_functions) {
+        if seen.insert(path.clone())
+            && let Some(file) = describe(&path, &route, false)
+        {
+            files.push(file);
+        }
+
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            continue;
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let file_name = file_name.to_string_lossy();
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut archives: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| is_rotated_archive(&entry.file_name().to_string_lossy(), &file_name))
+            .map(|entry| dir.join(entry.file_name()))
+            .collect();
+        archives.sort();
+        for archive in archives {
+            if seen.insert(archive.clone())
+                && let Some(file) = describe(&archive, &route, true)
+            {
+                files.push(file);
+            }
+        }
+    }
+    files
+}
+
+fn error_json(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({ "status": "error", "message": message.into() })),
+    )
+        .into_response()
+}
+
+fn attachment(name: &str) -> HeaderValue {
+    let name = name.replace(['"', '\\', '\r', '\n'], "_");
+    HeaderValue::from_str(&format!("attachment; filename=\"{}\"", name))
+        .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+/// List the downloadable files.
+async fn list_handler(route_functions: Vec<RouteFunction>) -> Response {
+    let files = tokio::task::spawn_blocking(move || downloadable_logs(&route_functions))
+        .await
+        .unwrap_or_default();
+    Json(json!({ "files": files })).into_response()
+}
+
+/// Download one file.  Supports `Range` requests.
+async fn download_handler(
+    route_functions: Vec<RouteFunction>,
+    Query(query): Query<DownloadQuery>,
+    req: Request,
+) -> Response {
+    let files = tokio::task::spawn_blocking(move || downloadable_logs(&route_functions))
+        .await
+        .unwrap_or_default();
+    let Some(file) = files.into_iter().find(|f| f.path == query.file) else {
+        return error_json(StatusCode::NOT_FOUND, "Unknown log file");
+    };
+
+    let path = PathBuf::from(&file.path);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let service = if name.ends_with(".gz") {
+        ServeFile::new_with_mime(&path, &"application/gzip".parse().expect("valid mime"))
+    } else {
+        ServeFile::new_with_mime(&path, &mime::TEXT_PLAIN_UTF_8)
+    };
+    match service.oneshot(req).await {
+        Ok(response) => {
+            let mut response = response.map(Body::new);
+            response
+                .headers_mut()
+                .insert(header::CONTENT_DISPOSITION, attachment(&name));
+            response
+        }
+        Err(e) => error_json(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Name each file inside a bundle, keeping names unique.
+fn bundle_names(files: &[DownloadableLog]) -> Vec<String> {
+    let mut used = HashSet::new();
+    files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let name = Path::new(&file.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| format!("log{}", i));
+            if used.insert(name.clone()) {
+                name
+            } else {
+                format!("{}-{}", i, name)
+            }
+        })
+        .collect()
+}
+
+fn write_bundle(
+    format: BundleFormat,
+    files: &[DownloadableLog],
+    writer: impl Write,
+) -> io::Result<()> {
+    let names = bundle_names(files);
+    match format {
+        BundleFormat::Zip => {
+            let mut zip = zip::ZipWriter::new_stream(writer);
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .large_file(true);
+            for (file, name) in files.iter().zip(names) {
+                let source = fs::File::open(&file.path)?;
+                let options = match source
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| zip::DateTime::try_from(OffsetDateTime::from(t)).ok())
+                {
+                    Some(modified) => options.last_modified_time(modified),
+                    None => options,
+                };
+                zip.start_file(name, options).map_err(io::Error::other)?;
+                io::copy(&mut &source, &mut zip)?;
+            }
+            zip.finish().map_err(io::Error::other)?;
+        }
+        BundleFormat::TarGz => {
+            let gz = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            let mut tar = tar::Builder::new(gz);
+            for (file, name) in files.iter().zip(names) {
+                // Logs may still be growing, so the entry is exactly the
+                // size seen at open time; a file truncated meanwhile is
+                // padded with zeros to keep the archive intact.
+                let source = fs::File::open(&file.path)?;
+                let meta = source.metadata()?;
+                let len = meta.len();
+                let mut header = tar::Header::new_gnu();
+                header.set_size(len);
+                header.set_mode(0o644);
+                header.set_mtime(
+                    meta.modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0),
+                );
+                header.set_cksum();
+                let data = source.take(len).chain(io::repeat(0)).take(len);
+                tar.append_data(&mut header, name, data)?;
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Stream a zip or tar.gz of several files, built as it is sent.
+///
+/// `?file=a&file=b&format=zip`; with no `file` every downloadable file is
+/// included.
+async fn bundle_handler(route_functions: Vec<RouteFunction>, RawQuery(raw): RawQuery) -> Response {
+    let params: Vec<(String, String)> =
+        serde_urlencoded::from_str(raw.as_deref().unwrap_or_default()).unwrap_or_default();
+    let format = match params
+        .iter()
+        .find(|(k, _)| k == "format")
+        .map(|(_, v)| v.as_str())
+    {
+        None | Some("zip") => BundleFormat::Zip,
+        Some("tar.gz") | Some("tgz") => BundleFormat::TarGz,
+        Some(other) => {
+            return error_json(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown bundle format '{}', use zip or tar.gz", other),
+            );
+        }
+    };
+    let requested: Vec<&str> = params
+        .iter()
+        .filter(|(k, _)| k == "file")
+        .map(|(_, v)| v.as_str())
+        .collect();
+
+    let files = tokio::task::spawn_blocking(move || downloadable_logs(&route_functions))
+        .await
+        .unwrap_or_default();
+    let files: Vec<DownloadableLog> = if requested.is_empty() {
+        files
+    } else {
+        let mut by_path: HashMap<&str, &DownloadableLog> =
+            files.iter().map(|f| (f.path.as_str(), f)).collect();
+        let mut selected = Vec::new();
+        for path in &requested {
+            match by_path.remove(path) {
+                Some(file) => selected.push(file.clone()),
+                None if files.iter().any(|f| f.path == *path) => {} // listed twice
+                None => {
+                    return error_json(
+                        StatusCode::NOT_FOUND,
+                        format!("Unknown log file: {}", path),
+                    );
+                }
+            }
+        }
+        selected
+    };
+    if files.is_empty() {
+        return error_json(StatusCode::NOT_FOUND, "No log files to bundle");
+    }
+
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = write_bundle(format, &files, SyncIoBridge::new(writer)) {
+            tracing::warn!("Failed to build log bundle: {}", e);
+        }
+    });
+
+    let stamp = OffsetDateTime::now_utc().unix_timestamp();
+    let (content_type, name) = match format {
+        BundleFormat::Zip => ("application/zip", format!("logs-{}.zip", stamp)),
+        BundleFormat::TarGz => ("application/gzip", format!("logs-{}.tar.gz", stamp)),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (header::CONTENT_DISPOSITION, attachment(&name)),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+        .into_response()
+}
+
+/// Routes for log downloads, nested under /protected/log_files.
+pub fn router(route_functions: Vec<RouteFunction>) -> Router<()> {
+    let list_routes = route_functions.clone();
+    let download_routes = route_functions.clone();
+    Router::new()
+        .route("/", get(move || list_handler(list_routes.clone())))
+        .route(
+            "/download",
+            get(move |query, req| download_handler(download_routes.clone(), query, req)),
+        )
+        .route(
+            "/bundle",
+            get(move |raw| bundle_handler(route_functions.clone(), raw)),
+        )
+}
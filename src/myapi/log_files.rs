@@ -0,0 +1,487 @@
+/// The get_logs route function.
+/// Reads log files natively, newest lines first, with paging and filtering,
+/// or follows them like `tail -F` with `follow=true`.
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::stream;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::htmlv::{HtmlV, RenderHtml};
+use crate::logging::reader::{LogFollower, lines_newest_first};
+use crate::logging::viewer::{parse_log_line, parse_time_param};
+use crate::my_api_config::{JournalSource, LogFileEntry};
+use crate::myapi::journal::{JournalEntry, JournalFollower, JournalReader, format_entry};
+
+const DEFAULT_LINES: usize = 50;
+const MAX_LINES: usize = 5000;
+
+/// Query parameters for get_logs routes.
+#[derive(Debug, Default, Deserialize)]
+pub struct GetLogsQuery {
+    /// log- index or name of the entry in log_file_types.  Defaults to the first.
+    log: Option<String>,
+    /// lines- number of lines to return.
+    lines: Option<usize>,
+    /// offset- number of matching lines to skip, counting back from the end.
+    offset: Option<usize>,
+    /// grep- only lines matching this regex.
+    grep: Option<String>,
+    /// since- only lines at or after this time.
+    since: Option<String>,
+    /// format- "json" for a JSON response, otherwise HTML.
+    format: Option<String>,
+    /// follow- stream lines appended to the log as server-sent events.
+    follow: Option<bool>,
+}
+
+/// A page of lines read from a log, in file order.
+struct LogPage {
+    lines: Vec<String>,
+    /// The structured entries behind `lines`, for journal sources.
+    entries: Option<Vec<JournalEntry>>,
+    has_more: bool,
+}
+
+/// Where a log is read from.
+enum LogSource {
+    File(PathBuf),
+    Journal(JournalSource),
+}
+
+/// Find the timestamp of a log line: the [LINE]/JSON formats, or an
+/// RFC 3339 time as the first word (optionally in brackets).
+fn line_time(line: &str) -> Option<OffsetDateTime> {
+    if let Some(time) = parse_log_line(line).time {
+        return OffsetDateTime::parse(&time, &Rfc3339).ok();
+    }
+    let first = line.split_whitespace().next()?;
+    let first = first.trim_start_matches('[').trim_end_matches(']');
+    OffsetDateTime::parse(first, &Rfc3339).ok()
+}
+
+/// Take a page from newest-first items, returned in file order, and
+/// whether there are older items left.
+fn take_page<T>(
+    newest_first: impl Iterator<Item = T>,
+    lines: usize,
+    offset: usize,
+) -> (Vec<T>, bool) {
+    let mut matching = newest_first.skip(offset);
+    let mut page: Vec<T> = matching.by_ref().take(lines).collect();
+    let has_more = matching.next().is_some();
+    page.reverse();
+    (page, has_more)
+}
+
+/// Read a page of lines, counting back from the end of the file.
+///
+/// Reading stops at the first line stamped before `since`, so only the
+/// tail of the file that is needed is read.
+fn read_log_page(
+    path: &Path,
+    lines: usize,
+    offset: usize,
+    grep: Option<&Regex>,
+    since: Option<OffsetDateTime>,
+) -> std::io::Result<LogPage> {
+    let matching = lines_newest_first(path)?
+        .take_while(|line| match since {
+            Some(since) => line_time(line).is_none_or(|time| time >= since),
+            None => true,
+        })
+        .filter(|line| grep.is_none_or(|re| re.is_match(line)));
+
+    let (page, has_more) = take_page(matching, lines, offset);
+    Ok(LogPage {
+        lines: page,
+        entries: None,
+        has_more,
+    })
+}
+
+/// Read a page of journal entries, counting back from the newest.
+/// `grep` is matched against the formatted line.
+fn read_journal_page(
+    source: &JournalSource,
+    lines: usize,
+    offset: usize,
+    grep: Option<&Regex>,
+    since: Option<OffsetDateTime>,
+) -> std::io::Result<LogPage> {
+    let mut reader = JournalReader::open(source, since)?;
+    let matching = reader
+        .by_ref()
+        .map(|entry| (format_entry(&entry), entry))
+        .filter(|(line, _)| grep.is_none_or(|re| re.is_match(line)));
+
+    let (page, has_more) = take_page(matching, lines, offset);
+    if let Some(error) = reader.error() {
+        return Err(std::io::Error::other(error));
+    }
+    let (lines, entries) = page.into_iter().unzip();
+    Ok(LogPage {
+        lines,
+        entries: Some(entries),
+        has_more,
+    })
+}
+
+/// Find the selected log by name or index.
+fn select_log<'a>(
+    logs: &'a [LogFileEntry],
+    selected: Option<&str>,
+) -> Option<(usize, &'a LogFileEntry)> {
+    match selected.filter(|s| !s.is_empty()) {
+        None => logs.first().map(|log| (0, log)),
+        Some(selected) => logs
+            .iter()
+            .enumerate()
+            .find(|(_, log)| log.name() == selected)
+            .or_else(|| {
+                selected
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| logs.get(i).map(|log| (i, log)))
+            }),
+    }
+}
+
+/// The `log` parameter for a log: its name, or its index if another log
+/// has the same name.
+fn log_param(logs: &[LogFileEntry], index: usize) -> String {
+    let name = logs[index].name();
+    if logs.iter().filter(|log| log.name() == name).count() > 1 {
+        index.to_string()
+    } else {
+        name
+    }
+}
+
+fn error_response(
+    status: StatusCode,
+    json_format: bool,
+    title: &str,
+    template: &str,
+    message: &str,
+) -> Response {
+    if json_format {
+        (
+            status,
+            Json(json!({ "status": "error", "message": message })),
+        )
+            .into_response()
+    } else {
+        (
+            status,
+            HtmlV(
+                (
+                    title,
+                    format!("<p>Error: {}</p>", html_escape::encode_text(message)),
+                )
+                    .render_html(template),
+            ),
+        )
+            .into_response()
+    }
+}
+
+/// Build the log selector and search form.
+fn render_controls(
+    logs: &[LogFileEntry],
+    selected: usize,
+    query: &GetLogsQuery,
+    lines: usize,
+) -> String {
+    let mut html = String::from("<form method=\"get\">\n<select name=\"log\">\n");
+    for (i, log) in logs.iter().enumerate() {
+        html.push_str(&format!(
+            "<option value=\"{}\"{}>{}</option>\n",
+            html_escape::encode_double_quoted_attribute(&log_param(logs, i)),
+            if i == selected { " selected" } else { "" },
+            html_escape::encode_text(&log.name()),
+        ));
+    }
+    html.push_str(&format!(
+        "</select>\n\
+         <input name=\"lines\" type=\"number\" min=\"1\" value=\"{}\">\n\
+         <input name=\"grep\" placeholder=\"regex\" value=\"{}\">\n\
+         <input name=\"since\" type=\"datetime-local\" value=\"{}\">\n\
+         <input type=\"submit\" value=\"Show\">\n</form>\n",
+        lines,
+        html_escape::encode_double_quoted_attribute(query.grep.as_deref().unwrap_or_default()),
+        html_escape::encode_double_quoted_attribute(query.since.as_deref().unwrap_or_default()),
+    ));
+    html
+}
+
+/// Link to the same query with a different offset.
+fn page_link(query: &GetLogsQuery, log: &str, lines: usize, offset: usize) -> String {
+    let mut params = vec![
+        ("log", log.to_string()),
+        ("lines", lines.to_string()),
+        ("offset", offset.to_string()),
+    ];
+    if let Some(grep) = &query.grep {
+        params.push(("grep", grep.clone()));
+    }
+    if let Some(since) = &query.since {
+        params.push(("since", since.clone()));
+    }
+    format!(
+        "?{}",
+        serde_urlencoded::to_string(params).unwrap_or_default()
+    )
+}
+
+/// Link to follow the same log and grep.
+fn follow_link(query: &GetLogsQuery, log: &str) -> String {
+    let mut params = vec![("log", log.to_string()), ("follow", "true".to_string())];
+    if let Some(grep) = &query.grep {
+        params.push(("grep", grep.clone()));
+    }
+    format!(
+        "?{}",
+        serde_urlencoded::to_string(params).unwrap_or_default()
+    )
+}
+
+/// Controls picked up by /static/log_follow.js in the default templates.
+fn render_follow_controls(follow_url: &str) -> String {
+    format!(
+        "<p class=\"log-follow\" data-follow-url=\"{}\">\n\
+         <input type=\"checkbox\" id=\"log-follow\"><label for=\"log-follow\">Follow</label>\n\
+         <button type=\"button\" id=\"log-pause\" disabled>Pause</button>\n\
+         <span id=\"log-follow-status\"></span>\n</p>\n",
+        html_escape::encode_double_quoted_attribute(follow_url)
+    )
+}
+
+/// Stream lines appended to `path` as server-sent events, one line per
+/// event, like `tail -F`.  Rotation and truncation are handled by
+/// [`LogFollower`] reopening the file.
+async fn follow_log(path: PathBuf, grep: Option<Regex>) -> Response {
+    let follower = LogFollower::new(path).await;
+    let events = stream::unfold(
+        (follower, grep, VecDeque::<String>::new()),
+        |(mut follower, grep, mut queue)| async move {
+            while queue.is_empty() {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                match follower.poll_lines().await {
+                    Ok(lines) => queue.extend(
+                        lines
+                            .into_iter()
+                            .filter(|line| grep.as_ref().is_none_or(|re| re.is_match(line))),
+                    ),
+                    Err(e) => tracing::debug!("get_logs follow poll failed: {}", e),
+                }
+            }
+            let line = queue.pop_front()?;
+            Some((
+                Ok::<_, Infallible>(Event::default().data(line)),
+                (follower, grep, queue),
+            ))
+        },
+    );
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Stream new journal entries as server-sent events, one formatted line
+/// per event.
+async fn follow_journal(source: JournalSource, grep: Option<Regex>) -> std::io::Result<Response> {
+    let follower = JournalFollower::new(&source)?;
+    let events = stream::unfold((follower, grep), |(mut follower, grep)| async move {
+        loop {
+            let line = format_entry(&follower.next_entry().await?);
+            if grep.as_ref().is_none_or(|re| re.is_match(&line)) {
+                return Some((
+                    Ok::<_, Infallible>(Event::default().data(line)),
+                    (follower, grep),
+                ));
+            }
+        }
+    });
+
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// for the Log Get Handler:
+pub async fn get_logs_handler(
+    Query(query): Query<GetLogsQuery>,
+    log_file_types: Option<Vec<LogFileEntry>>,
+    title: String,
+    template: String,
+) -> Response {
+    let json_format = query.format.as_deref() == Some("json");
+
+    let logs = log_file_types.unwrap_or_default();
+    let Some((selected, log)) = select_log(&logs, query.log.as_deref()) else {
+        let message = if logs.is_empty() {
+            "No log files are configured for this route"
+        } else {
+            "Unknown log"
+        };
+        return error_response(
+            StatusCode::NOT_FOUND,
+            json_format,
+            &title,
+            &template,
+            message,
+        );
+    };
+
+    let lines = query.lines.unwrap_or(DEFAULT_LINES).clamp(1, MAX_LINES);
+    let offset = query.offset.unwrap_or(0);
+    let grep = match query.grep.as_deref().filter(|g| !g.is_empty()) {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    json_format,
+                    &title,
+                    &template,
+                    &format!("Invalid grep pattern: {}", e),
+                );
+            }
+        },
+        None => None,
+    };
+    let since = match query.since.as_deref().filter(|s| !s.is_empty()) {
+        Some(since) => match parse_time_param(since) {
+            Some(time) => Some(time),
+            None => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    json_format,
+                    &title,
+                    &template,
+                    &format!("Invalid since time: {}", since),
+                );
+            }
+        },
+        None => None,
+    };
+
+    let source = match (log, log.path()) {
+        (LogFileEntry::Journal { journal, .. }, _) => LogSource::Journal(journal.clone()),
+        (_, path) => LogSource::File(PathBuf::from(
+            shellexpand::tilde(path.unwrap_or_default()).to_string(),
+        )),
+    };
+    if query.follow.unwrap_or(false) {
+        return match source {
+            LogSource::File(path) => follow_log(path, grep).await,
+            LogSource::Journal(journal) => match follow_journal(journal, grep).await {
+                Ok(response) => response,
+                Err(e) => error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json_format,
+                    &title,
+                    &template,
+                    &format!("Failed to start journalctl: {}", e),
+                ),
+            },
+        };
+    }
+
+    let page = {
+        let grep = grep.clone();
+        tokio::task::spawn_blocking(move || match &source {
+            LogSource::File(path) => read_log_page(path, lines, offset, grep.as_ref(), since),
+            LogSource::Journal(journal) => {
+                read_journal_page(journal, lines, offset, grep.as_ref(), since)
+            }
+        })
+        .await
+    };
+    let page = match page {
+        Ok(Ok(page)) => page,
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to read log {}: {}", log.name(), e);
+            return error_response(
+                StatusCode::NOT_FOUND,
+                json_format,
+                &title,
+                &template,
+                &format!("Failed to read {}: {}", log.name(), e),
+            );
+        }
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json_format,
+                &title,
+                &template,
+                &e.to_string(),
+            );
+        }
+    };
+
+    if json_format {
+        let mut response = json!({
+            "log": log.name(),
+            "index": selected,
+            "offset": offset,
+            "has_more": page.has_more,
+            "lines": page.lines,
+        });
+        if let Some(entries) = page.entries {
+            response["entries"] = json!(entries);
+        }
+        return Json(response).into_response();
+    }
+
+    let log_param = log_param(&logs, selected);
+    let mut body = render_controls(&logs, selected, &query, lines);
+    body.push_str(&render_follow_controls(&follow_link(&query, &log_param)));
+    let log_lines = page
+        .lines
+        .iter()
+        .map(|line| format!("<li>{}</li>", html_escape::encode_text(line)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    body.push_str(&format!("<ul id=\"log-lines\">{}</ul>\n<p>", log_lines));
+    if page.has_more {
+        body.push_str(&format!(
+            "<a href=\"{}\">Older</a> ",
+            html_escape::encode_double_quoted_attribute(&page_link(
+                &query,
+                &log_param,
+                lines,
+                offset + lines
+            ))
+        ));
+    }
+    if offset > 0 {
+        body.push_str(&format!(
+            "<a href=\"{}\">Newer</a>",
+            html_escape::encode_double_quoted_attribute(&page_link(
+                &query,
+                &log_param,
+                lines,
+                offset.saturating_sub(lines)
+            ))
+        ));
+    }
+    body.push_str("</p>");
+
+    HtmlV((title, body).render_html(&template)).into_response()
+}
@@ -0,0 +1,195 @@
+/// Renders markdown_page bodies: CommonMark with tables, strikethrough,
+/// task lists and footnotes.  Headings get ids and anchor links, fenced
+/// code blocks are highlighted by language, and a table of contents can
+/// be put above the page.
+///
+/// Highlighting uses CSS classes, styled by /static/markdown.css.
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use pulldown_cmark::{
+    CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd, html,
+};
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// A heading, for the table of contents.
+struct Heading {
+    level: HeadingLevel,
+    id: String,
+    text: String,
+}
+
+/// Render markdown into HTML, with a table of contents first if `toc`.
+pub fn render_markdown(source: &str, toc: bool) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+    let mut events: Vec<Event> = Parser::new_ext(source, options).collect();
+
+    let headings = add_heading_anchors(&mut events);
+    let events = highlight_code_blocks(events);
+
+    let mut out = String::from("<link rel=\"stylesheet\" href=\"/static/markdown.css\">\n");
+    out.push_str("<div class=\"markdown-body\">\n");
+    if toc && !headings.is_empty() {
+        out.push_str(&build_toc(&headings));
+    }
+    html::push_html(&mut out, events.into_iter());
+    out.push_str("</div>\n");
+    out
+}
+
+/// An id for a heading: lowercase letters, digits and dashes.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Give every heading an id, unless it set one with `{#id}`, and a link
+/// to itself.  Returns the headings in order.
+fn add_heading_anchors(events: &mut Vec<Event>) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::Heading { level, id, .. }) = &events[i] else {
+            i += 1;
+            continue;
+        };
+        let level = *level;
+        let given_id = id.as_ref().map(|id| id.to_string());
+
+        // the heading's text, up to its end
+        let start = i;
+        let mut text = String::new();
+        while i < events.len() && !matches!(events[i], Event::End(TagEnd::Heading(_))) {
+            if let Event::Text(t) | Event::Code(t) = &events[i] {
+                text.push_str(t);
+            }
+            i += 1;
+        }
+
+        let base = given_id.unwrap_or_else(|| slugify(&text));
+        let count = used.entry(base.clone()).or_insert(0);
+        let id = if *count == 0 {
+            base
+        } else {
+            format!("{}-{}", base, count)
+        };
+        *count += 1;
+
+        if let Event::Start(Tag::Heading { id: heading_id, .. }) = &mut events[start] {
+            *heading_id = Some(CowStr::from(id.clone()));
+        }
+        events.insert(
+            i,
+            Event::InlineHtml(CowStr::from(format!(
+                "<a class=\"heading-anchor\" href=\"#{}\">#</a>",
+                html_escape::encode_double_quoted_attribute(&id)
+            ))),
+        );
+        // past the anchor and the end of the heading
+        i += 2;
+
+        headings.push(Heading { level, id, text });
+    }
+    headings
+}
+
+/// Replace fenced code blocks in a known language with highlighted HTML.
+/// Other code blocks are left as they are.
+fn highlight_code_blocks(events: Vec<Event>) -> Vec<Event> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut iter = events.into_iter();
+    while let Some(event) = iter.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &event else {
+            out.push(event);
+            continue;
+        };
+        // the language is the first word of the info string, e.g. "rust" in "rust,ignore"
+        let lang = info
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .next()
+            .unwrap_or_default();
+        let Some(syntax) = (!lang.is_empty())
+            .then(|| SYNTAXES.find_syntax_by_token(lang))
+            .flatten()
+        else {
+            out.push(event);
+            continue;
+        };
+
+        let mut code = String::new();
+        for inner in iter.by_ref() {
+            match inner {
+                Event::End(TagEnd::CodeBlock) => break,
+                Event::Text(text) => code.push_str(&text),
+                _ => {}
+            }
+        }
+
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+        for line in LinesWithEndings::from(&code) {
+            if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+                tracing::warn!("Failed to highlight {} code block: {}", lang, e);
+                break;
+            }
+        }
+        out.push(Event::Html(CowStr::from(format!(
+            "<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>\n",
+            html_escape::encode_double_quoted_attribute(lang),
+            generator.finalize()
+        ))));
+    }
+    out
+}
+
+/// A nested list of links to the headings.
+fn build_toc(headings: &[Heading]) -> String {
+    let mut html = String::from("<fieldset class=\"markdown-toc\">\n<legend>Contents</legend>\n");
+    // levels of the lists that are open
+    let mut open: Vec<HeadingLevel> = Vec::new();
+    for heading in headings {
+        while open.last().is_some_and(|level| *level > heading.level) {
+            html.push_str("</li>\n</ul>\n");
+            open.pop();
+        }
+        if open.last().is_some_and(|level| *level == heading.level) {
+            html.push_str("</li>\n");
+        } else {
+            html.push_str("<ul>\n");
+            open.push(heading.level);
+        }
+        html.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&heading.id),
+            html_escape::encode_text(&heading.text)
+        ));
+    }
+    for _ in open {
+        html.push_str("</li>\n</ul>\n");
+    }
+    html.push_str("</fieldset>\n");
+    html
+}
@@ -1,16 +1,18 @@
 use crate::auth::users::{AuthSession, User};
 use crate::htmlv::{HtmlV, RenderHtml};
+use crate::metrics;
 use crate::state;
 use axum::Json;
 use axum::response::IntoResponse;
 use serde_json::Value as JsonValue;
 
-use shellexpand;
 use state::AppSingleton;
+use std::collections::HashMap;
 use std::process::{Command, Stdio};
-use std::{collections::HashMap, sync::Arc};
+use std::time::Instant;
 use tokio::process::Command as TokioCommand;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 
 use tokio::sync::Mutex;
 
@@ -55,10 +57,7 @@ pub async fn stop_script(script_path: &str) -> Result<(), std::io::Error> {
                 println!("Kill signal sent for {}", script_path);
                 Ok(())
             }
-            Err(_) => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to send kill signal",
-            )),
+            Err(_) => Err(std::io::Error::other("Failed to send kill signal")),
         }
     } else {
         Err(std::io::Error::new(
@@ -116,12 +115,22 @@ pub async fn spawn_script_in_background(
                 .insert(script_path.to_string(), kill_tx);
 
             let script_path_clone = script_path.to_string();
+            let started = Instant::now();
 
             tokio::spawn(async move {
                 tokio::select! {
                     status = child.wait() => {
                         match status {
                             Ok(status) => {
+                                let exit_status = status
+                                    .code()
+                                    .map(|code| code.to_string())
+                                    .unwrap_or_else(|| "signal".to_string());
+                                metrics::observe_script(
+                                    &script_path_clone,
+                                    &exit_status,
+                                    started.elapsed().as_secs_f64(),
+                                );
                                 // Call a static handler function here
                                 script_finished_handler(&script_path_clone, status);
                                 SCRIPT_KILL_SENDERS.lock().await.remove(&script_path_clone);
@@ -148,13 +157,18 @@ pub async fn spawn_script_in_background(
                         println!("Script {} was killed.", script_path_clone);
                         tracing::debug!("Script {} was killed.", script_path_clone);
 
+                        metrics::observe_script(
+                            &script_path_clone,
+                            "killed",
+                            started.elapsed().as_secs_f64(),
+                        );
                         app.insert_status(&script_path_clone, "Killed...");
                         SCRIPT_KILL_SENDERS.lock().await.remove(&script_path_clone);
                     }
                 }
-            });
+            }.in_current_span());
 
-            tracing::debug!("Script {} is running in the background.", script_path);
+            tracing::info!("Script {} is running in the background.", script_path);
             Ok(format!("Script {} is running.", script_path))
         }
 
@@ -171,7 +185,7 @@ pub async fn run_command_handler(
     log: String,
     script: String,
     title: String,
-    template: i32,
+    template: String,
 ) -> HtmlV<String> {
     let lock_file_path = shellexpand::tilde(&lock).to_string();
     let log_file_path = shellexpand::tilde(&log).to_string();
@@ -184,30 +198,33 @@ pub async fn run_command_handler(
     );
     match try_acquire_lock(&lock_file_path).await {
         Ok(true) => {
-            let handle = tokio::spawn(async move {
-                match spawn_script_in_background(&script_file_path, &log_file_path).await {
-                    Ok(msg) => msg,
-                    Err(e) => format!("Failed to run script: {}", e),
+            let handle = tokio::spawn(
+                async move {
+                    match spawn_script_in_background(&script_file_path, &log_file_path).await {
+                        Ok(msg) => msg,
+                        Err(e) => format!("Failed to run script: {}", e),
+                    }
                 }
-            });
+                .in_current_span(),
+            );
 
             let mywork = match handle.await {
                 Ok(result) => result,
                 Err(e) => format!("Failed to execute async task: {}", e),
             };
 
-            HtmlV((title, format!("<p>{}</p>", mywork)).render_html_from_int(template))
+            HtmlV((title, format!("<p>{}</p>", mywork)).render_html(&template))
         }
         Ok(false) => HtmlV(
             (
                 title,
                 "<p>This script is already running...</p>".to_string(),
             )
-                .render_html_from_int(template),
-        ),
-        Err(e) => HtmlV(
-            (title, format!("<p>Error acquiring lock: {}</p>", e)).render_html_from_int(template),
+                .render_html(&template),
         ),
+        Err(e) => {
+            HtmlV((title, format!("<p>Error acquiring lock: {}</p>", e)).render_html(&template))
+        }
     }
 }
 
@@ -218,7 +235,7 @@ pub async fn run_command_handler_secure_wrap(
     log: String,
     script: String,
     title: String,
-    template: i32,
+    template: String,
 ) -> HtmlV<String> {
     let lock_file_path = shellexpand::tilde(&lock).to_string();
     let log_file_path = shellexpand::tilde(&log).to_string();
@@ -231,21 +248,22 @@ pub async fn run_command_handler_secure_wrap(
     );
     match try_acquire_lock(&lock_file_path).await {
         Ok(true) => {
-            let handle = tokio::spawn(async move {
-                match spawn_script_in_background(&script_file_path, &log_file_path).await {
-                    Ok(msg) => msg,
-                    Err(e) => format!("Failed to run script: {}", e),
+            let handle = tokio::spawn(
+                async move {
+                    match spawn_script_in_background(&script_file_path, &log_file_path).await {
+                        Ok(msg) => msg,
+                        Err(e) => format!("Failed to run script: {}", e),
+                    }
                 }
-            });
+                .in_current_span(),
+            );
 
             let mywork = match handle.await {
                 Ok(result) => result,
                 Err(e) => format!("Failed to execute async task: {}", e),
             };
 
-            HtmlV(
-                (title, format!("<p>{}</p>", mywork), user.username).render_html_from_int(template),
-            )
+            HtmlV((title, format!("<p>{}</p>", mywork), user.username).render_html(&template))
         }
         Ok(false) => HtmlV(
             (
@@ -253,7 +271,7 @@ pub async fn run_command_handler_secure_wrap(
                 "<p>This script is already running...</p>".to_string(),
                 user.username,
             )
-                .render_html_from_int(template),
+                .render_html(&template),
         ),
         Err(e) => HtmlV(
             (
@@ -261,7 +279,7 @@ pub async fn run_command_handler_secure_wrap(
                 format!("<p>Error acquiring lock: {}</p>", e),
                 user.username,
             )
-                .render_html_from_int(template),
+                .render_html(&template),
         ),
     }
 }
@@ -273,7 +291,7 @@ pub async fn run_command_handler_secure(
     log: String,
     script: String,
     title: String,
-    template: i32,
+    template: String,
 ) -> HtmlV<String> {
     match auth_session.user {
         Some(user) => {
@@ -289,16 +307,16 @@ pub async fn run_command_handler_secure(
         }
         None => {
             let error_message = "Internal Server Error-insufficient perms";
-            HtmlV((title, format!("{}", error_message)).render_html_from_int(-1))
+            HtmlV((title, error_message.to_string()).render_html_from_int(-1))
         }
     }
 }
 
 /// Secure variant of run command.
-pub async fn get_command_statuses(title: String, template: i32) -> HtmlV<String> {
+pub async fn get_command_statuses(title: String, template: String) -> HtmlV<String> {
     let app = AppSingleton::instance();
     let hashjson = app.hashstatus_to_json();
-    HtmlV((title, format!("<p>{}</p>", hashjson)).render_html_from_int(template))
+    HtmlV((title, format!("<p>{}</p>", hashjson)).render_html(&template))
 }
 /// Secure variant of run command.
 pub async fn get_command_statuses_secure(auth_session: AuthSession) -> impl IntoResponse {
@@ -1,39 +1,101 @@
 /*
 
-Module that sends out current cpu and memory usage.
+Module that sends out current cpu and memory usage, and optionally
+disk, network, temperature, load, uptime and process details.
+
+CPU, RAM and swap come from one shared System, refreshed by a single
+background sampler.  Its snapshots are served by /procmon and pushed to
+the status bar over /procmon/stream, so open tabs don't add load.
 
 */
 
-use axum::response::{IntoResponse, Json};
+pub(crate) mod alerts;
+pub(crate) mod history;
+pub(crate) mod processes;
+pub(crate) mod sections;
+
+use axum::{
+    Router,
+    extract::Query,
+    http::StatusCode,
+    response::{
+        IntoResponse, Json,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use futures_util::stream;
 use serde::Serialize;
-use sysinfo::{CpuRefreshKind, RefreshKind, System};
-use tokio::sync::Mutex;
+use serde_json::json;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use tokio::sync::watch;
 
 use once_cell::sync::Lazy;
 
+use crate::metrics::{header, labels};
+use sections::{DiskUsage, LoadAverage, NetworkUsage, ProcmonQuery, Sections, Temperature};
+
 #[derive(Serialize)]
 struct SystemUsage {
     cpu_usage: Vec<f32>,
     ram_usage: f32,
     swap_usage: f32,
+    // optional sections, only present when included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disks: Option<Vec<DiskUsage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    networks: Option<Vec<NetworkUsage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperatures: Option<Vec<Temperature>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load: Option<LoadAverage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uptime_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    process_count: Option<usize>,
+}
+/// CPU, RAM and swap usage, as sampled by the live sampler.
+#[derive(Clone, Default, Serialize)]
+struct LiveUsage {
+    cpu_usage: Vec<f32>,
+    ram_usage: f32,
+    swap_usage: f32,
 }
+
+/// The latest usage, and the same as JSON, so stream subscribers don't
+/// each serialize it again.
+struct Snapshot {
+    usage: LiveUsage,
+    json: String,
+}
+
+impl Snapshot {
+    fn new(usage: LiveUsage) -> Self {
+        let json = serde_json::to_string(&usage).unwrap_or_default();
+        Self { usage, json }
+    }
+}
+
 static SYS: Lazy<Mutex<System>> = Lazy::new(|| {
     Mutex::new(System::new_with_specifics(
-        RefreshKind::nothing().with_cpu(CpuRefreshKind::everything()),
+        RefreshKind::nothing()
+            .with_cpu(CpuRefreshKind::everything())
+            .with_memory(MemoryRefreshKind::everything()),
     ))
 });
-async fn get_cpu_usage() -> Vec<f32> {
-    let mut sys = SYS.lock().await;
-
-    sys.refresh_cpu_usage();
+static LIVE: Lazy<watch::Sender<Arc<Snapshot>>> =
+    Lazy::new(|| watch::channel(Arc::new(Snapshot::new(LiveUsage::default()))).0);
+static LIVE_INTERVAL: OnceLock<u64> = OnceLock::new();
 
+fn get_cpu_usage(sys: &System) -> Vec<f32> {
     sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect()
 }
 
-async fn get_memory_usage() -> f32 {
-    let mut sys = System::new_all();
-    sys.refresh_memory();
-
+fn get_memory_usage(sys: &System) -> f32 {
     let total = sys.total_memory() as f32;
     let free = sys.available_memory() as f32;
     let used = total - free;
@@ -45,10 +107,7 @@ async fn get_memory_usage() -> f32 {
     }
 }
 
-async fn get_swap_usage() -> f32 {
-    let mut sys = System::new_all();
-    sys.refresh_memory();
-
+fn get_swap_usage(sys: &System) -> f32 {
     let swap_total = sys.total_swap() as f32;
     let swap_free = sys.free_swap() as f32;
     let swap_used = swap_total - swap_free;
@@ -60,19 +119,271 @@ async fn get_swap_usage() -> f32 {
     }
 }
 
-async fn get_system_usage() -> SystemUsage {
-    let cpu_usage = get_cpu_usage().await;
-    let ram_usage = get_memory_usage().await;
-    let swap_usage = get_swap_usage().await;
+fn refresh_usage() -> LiveUsage {
+    let mut sys = SYS.lock().unwrap();
+    sys.refresh_cpu_usage();
+    sys.refresh_memory();
+    LiveUsage {
+        cpu_usage: get_cpu_usage(&sys),
+        ram_usage: get_memory_usage(&sys),
+        swap_usage: get_swap_usage(&sys),
+    }
+}
+
+/// Start the live sampler, which refreshes usage every `interval_ms` for
+/// every subscriber.  Should be called once at startup.
+pub fn start_live_sampler(interval_ms: u64) {
+    // CPU usage needs some time between refreshes to be meaningful
+    let interval_ms = interval_ms.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL.as_millis() as u64);
+    if LIVE_INTERVAL.set(interval_ms).is_err() {
+        tracing::warn!("procmon live sampler was already started");
+        return;
+    }
+    tracing::info!("procmon live usage: every {}ms", interval_ms);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(interval_ms));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            let usage = refresh_usage();
+            LIVE.send_replace(Arc::new(Snapshot::new(usage)));
+        }
+    });
+}
+
+/// The latest sampled usage, without the optional sections.
+fn get_system_usage() -> SystemUsage {
+    let snapshot = LIVE.borrow().clone();
+    let LiveUsage {
+        cpu_usage,
+        ram_usage,
+        swap_usage,
+    } = snapshot.usage.clone();
 
     SystemUsage {
         cpu_usage,
         ram_usage,
         swap_usage,
+        disks: None,
+        networks: None,
+        temperatures: None,
+        load: None,
+        uptime_secs: None,
+        process_count: None,
+    }
+}
+
+/// Add the requested optional sections.
+async fn add_sections(usage: &mut SystemUsage, sections: Sections) {
+    if sections.disks {
+        usage.disks = Some(sections::disks().await);
+    }
+    if sections.networks {
+        usage.networks = Some(sections::networks().await);
+    }
+    if sections.temperatures {
+        usage.temperatures = Some(sections::temperatures().await);
+    }
+    if sections.load {
+        usage.load = Some(sections::load());
+    }
+    if sections.uptime {
+        usage.uptime_secs = Some(sections::uptime());
+    }
+    if sections.processes {
+        usage.process_count = Some(sections::process_count().await);
+    }
+}
+
+/// Current usage.  `?include=disks,networks,...` or `?include=all` adds
+/// the optional sections.
+pub async fn system_usage_handler(Query(query): Query<ProcmonQuery>) -> impl IntoResponse {
+    let sections = match Sections::from_query(&query) {
+        Ok(sections) => sections,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "status": "error", "message": e })),
+            )
+                .into_response();
+        }
+    };
+    let mut usage = get_system_usage();
+    add_sections(&mut usage, sections).await;
+    Json(usage).into_response()
+}
+
+/// Live CPU, RAM and swap usage as server-sent events, one JSON snapshot
+/// per sample, starting with the latest.
+pub async fn system_usage_stream() -> impl IntoResponse {
+    let mut rx = LIVE.subscribe();
+    rx.mark_changed();
+    let events = stream::unfold(rx, |mut rx| async move {
+        rx.changed().await.ok()?;
+        let json = rx.borrow_and_update().json.clone();
+        Some((Ok::<_, Infallible>(Event::default().data(json)), rx))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Current usage as Prometheus gauges, for /metrics.  Network totals are
+/// counters, since Prometheus works out rates itself.
+pub async fn prometheus_gauges() -> String {
+    let usage = get_system_usage();
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "procmon_cpu_usage_percent",
+        "gauge",
+        "CPU usage per core.",
+    );
+    for (core, cpu) in usage.cpu_usage.iter().enumerate() {
+        let core = core.to_string();
+        let _ = writeln!(
+            out,
+            "procmon_cpu_usage_percent{} {}",
+            labels(&[("core", &core)]),
+            cpu
+        );
+    }
+    header(
+        &mut out,
+        "procmon_ram_usage_percent",
+        "gauge",
+        "RAM in use.",
+    );
+    let _ = writeln!(out, "procmon_ram_usage_percent {}", usage.ram_usage);
+    header(
+        &mut out,
+        "procmon_swap_usage_percent",
+        "gauge",
+        "Swap in use.",
+    );
+    let _ = writeln!(out, "procmon_swap_usage_percent {}", usage.swap_usage);
+
+    let load = sections::load();
+    header(
+        &mut out,
+        "procmon_load_average",
+        "gauge",
+        "System load average.",
+    );
+    for (period, value) in [("1m", load.one), ("5m", load.five), ("15m", load.fifteen)] {
+        let _ = writeln!(
+            out,
+            "procmon_load_average{} {}",
+            labels(&[("period", period)]),
+            value
+        );
+    }
+    header(
+        &mut out,
+        "procmon_uptime_seconds",
+        "gauge",
+        "System uptime.",
+    );
+    let _ = writeln!(out, "procmon_uptime_seconds {}", sections::uptime());
+    header(&mut out, "procmon_processes", "gauge", "Running processes.");
+    let _ = writeln!(out, "procmon_processes {}", sections::process_count().await);
+
+    let disks = sections::disks().await;
+    header(
+        &mut out,
+        "procmon_disk_usage_percent",
+        "gauge",
+        "Disk space in use.",
+    );
+    for disk in &disks {
+        let _ = writeln!(
+            out,
+            "procmon_disk_usage_percent{} {}",
+            labels(&[("mount_point", &disk.mount_point)]),
+            disk.usage
+        );
+    }
+    header(
+        &mut out,
+        "procmon_disk_available_bytes",
+        "gauge",
+        "Disk space available.",
+    );
+    for disk in &disks {
+        let _ = writeln!(
+            out,
+            "procmon_disk_available_bytes{} {}",
+            labels(&[("mount_point", &disk.mount_point)]),
+            disk.available_bytes
+        );
+    }
+
+    header(
+        &mut out,
+        "procmon_temperature_celsius",
+        "gauge",
+        "Sensor temperatures.",
+    );
+    for temperature in sections::temperatures().await {
+        let _ = writeln!(
+            out,
+            "procmon_temperature_celsius{} {}",
+            labels(&[("sensor", &temperature.label)]),
+            temperature.celsius
+        );
+    }
+
+    let networks = sections::networks().await;
+    header(
+        &mut out,
+        "procmon_network_received_bytes_total",
+        "counter",
+        "Bytes received.",
+    );
+    for network in &networks {
+        let _ = writeln!(
+            out,
+            "procmon_network_received_bytes_total{} {}",
+            labels(&[("interface", &network.interface)]),
+            network.total_rx_bytes
+        );
+    }
+    header(
+        &mut out,
+        "procmon_network_transmitted_bytes_total",
+        "counter",
+        "Bytes sent.",
+    );
+    for network in &networks {
+        let _ = writeln!(
+            out,
+            "procmon_network_transmitted_bytes_total{} {}",
+            labels(&[("interface", &network.interface)]),
+            network.total_tx_bytes
+        );
+    }
+
+    header(
+        &mut out,
+        "procmon_alert_firing",
+        "gauge",
+        "Alert rules that are firing.",
+    );
+    for alert in alerts::active_alerts() {
+        let _ = writeln!(
+            out,
+            "procmon_alert_firing{} 1",
+            labels(&[("rule", &alert.rule)])
+        );
     }
+    out
 }
 
-pub async fn system_usage_handler() -> impl IntoResponse {
-    let usage = get_system_usage().await;
-    Json(usage)
+/// Routes for the procmon pages, nested under /protected/procmon.
+pub fn router() -> Router<()> {
+    Router::new()
+        .route("/", get(history::history_page))
+        .route("/processes", get(processes::processes_page))
+        .route("/processes/list", get(processes::processes_handler))
+        .route("/processes/signal", post(processes::signal_handler))
 }
@@ -0,0 +1,428 @@
+/*
+
+Threshold alerts for procmon.  Rules from config.yaml are checked each
+time the history sampler takes a sample; alerts fire once a threshold has
+been crossed for long enough and resolve when it no longer is.  Changes
+are sent to the configured notifiers and kept in thisbackend.db.
+
+*/
+use axum::{Json, extract::Query, response::IntoResponse};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use super::history::Sample;
+use super::sections::{self, DiskUsage, Temperature};
+use crate::config::{AlertMetric, AlertNotifier, AlertOp, AlertRule, AlertsConfig};
+use crate::secrets;
+
+/// Events returned by /procmon/alerts when no `limit` is given.
+const DEFAULT_HISTORY: u32 = 50;
+
+/// Most events returned at all.
+const MAX_HISTORY: u32 = 500;
+
+/// How long a webhook or command may take.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+static ALERTS: OnceLock<AlertsConfig> = OnceLock::new();
+static ALERT_DB: OnceLock<SqlitePool> = OnceLock::new();
+static STATES: Lazy<Mutex<HashMap<String, RuleState>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+/// A firing alert.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveAlert {
+    pub rule: String,
+    pub metric: AlertMetric,
+    /// the disk or sensor, for disk and temperature rules
+    pub target: Option<String>,
+    pub value: f64,
+    pub op: AlertOp,
+    pub threshold: f64,
+    /// unix seconds the threshold was first crossed
+    pub since: i64,
+    pub message: String,
+}
+
+/// An alert firing or resolving, as stored and sent to notifiers.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AlertEvent {
+    /// unix seconds
+    pub ts: i64,
+    pub rule: String,
+    /// "firing" or "resolved"
+    pub state: String,
+    pub value: f64,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct RuleState {
+    breaching_since: Option<i64>,
+    firing: Option<ActiveAlert>,
+}
+
+/// Readings that are only gathered when a rule needs them.
+#[derive(Default)]
+struct Readings {
+    disks: Vec<DiskUsage>,
+    temperatures: Vec<Temperature>,
+    load: f64,
+}
+
+fn metric_name(metric: AlertMetric) -> &'static str {
+    match metric {
+        AlertMetric::Cpu => "cpu",
+        AlertMetric::Ram => "ram",
+        AlertMetric::Swap => "swap",
+        AlertMetric::Disk => "disk",
+        AlertMetric::Temperature => "temperature",
+        AlertMetric::Load => "load",
+    }
+}
+
+fn op_symbol(op: AlertOp) -> &'static str {
+    match op {
+        AlertOp::Above => ">",
+        AlertOp::Below => "<",
+    }
+}
+
+fn describe(rule: &AlertRule, target: Option<&str>, value: f64) -> String {
+    match target {
+        Some(target) => format!("{} '{}' is {:.1}", metric_name(rule.metric), target, value),
+        None => format!("{} is {:.1}", metric_name(rule.metric), value),
+    }
+}
+
+/// Load the rules and the state of alerts that were firing when the server
+/// stopped.  Should be called once at startup, before the history sampler.
+pub async fn init_alerts(config: AlertsConfig, db: SqlitePool) {
+    let mut names = HashSet::new();
+    let rules: Vec<AlertRule> = config
+        .rules
+        .into_iter()
+        .filter(|rule| {
+            let unique = !rule.name.is_empty() && names.insert(rule.name.clone());
+            if !unique {
+                tracing::error!(
+                    "Skipping alert rule with an empty or duplicate name '{}'",
+                    rule.name
+                );
+            }
+            unique
+        })
+        .collect();
+    if !rules.is_empty() {
+        tracing::info!(
+            "Loaded {} alert rule(s) and {} notifier(s)",
+            rules.len(),
+            config.notifiers.len()
+        );
+    }
+
+    let latest: Result<Vec<AlertEvent>, sqlx::Error> = sqlx::query_as(
+        "SELECT ts, rule, state, value, message FROM alert_events
+         WHERE id IN (SELECT MAX(id) FROM alert_events GROUP BY rule)",
+    )
+    .fetch_all(&db)
+    .await;
+    match latest {
+        Ok(events) => {
+            let mut states = STATES.lock().unwrap();
+            for event in events.into_iter().filter(|e| e.state == "firing") {
+                let Some(rule) = rules.iter().find(|r| r.name == event.rule) else {
+                    continue;
+                };
+                states.insert(
+                    rule.name.clone(),
+                    RuleState {
+                        breaching_since: Some(event.ts),
+                        firing: Some(ActiveAlert {
+                            rule: rule.name.clone(),
+                            metric: rule.metric,
+                            target: None,
+                            value: event.value,
+                            op: rule.op,
+                            threshold: rule.threshold,
+                            since: event.ts,
+                            message: event.message,
+                        }),
+                    },
+                );
+            }
+        }
+        Err(e) => tracing::error!("Failed to load alert history: {}", e),
+    }
+
+    let config = AlertsConfig {
+        rules,
+        notifiers: config.notifiers,
+    };
+    if ALERTS.set(config).is_err() || ALERT_DB.set(db).is_err() {
+        tracing::warn!("Alerts were already initialized");
+    }
+}
+
+async fn readings(rules: &[AlertRule]) -> Readings {
+    let needs = |metric| rules.iter().any(|r| r.metric == metric);
+    Readings {
+        disks: if needs(AlertMetric::Disk) {
+            sections::disks().await
+        } else {
+            Vec::new()
+        },
+        temperatures: if needs(AlertMetric::Temperature) {
+            sections::temperatures().await
+        } else {
+            Vec::new()
+        },
+        load: sections::load().one,
+    }
+}
+
+/// The rule's value and which disk or sensor it came from.  With several,
+/// the one furthest past the threshold is used.  None if nothing matches.
+fn value_of(
+    rule: &AlertRule,
+    sample: &Sample,
+    readings: &Readings,
+) -> Option<(f64, Option<String>)> {
+    let candidates: Vec<(f64, Option<String>)> = match rule.metric {
+        AlertMetric::Cpu => vec![(sample.cpu as f64, None)],
+        AlertMetric::Ram => vec![(sample.ram as f64, None)],
+        AlertMetric::Swap => vec![(sample.swap as f64, None)],
+        AlertMetric::Load => vec![(readings.load, None)],
+        AlertMetric::Disk => readings
+            .disks
+            .iter()
+            .filter(|d| rule.target.as_ref().is_none_or(|t| d.mount_point == *t))
+            .map(|d| (d.usage as f64, Some(d.mount_point.clone())))
+            .collect(),
+        AlertMetric::Temperature => readings
+            .temperatures
+            .iter()
+            .filter(|t| {
+                rule.target
+                    .as_ref()
+                    .is_none_or(|target| t.label.to_lowercase().contains(&target.to_lowercase()))
+            })
+            .map(|t| (t.celsius as f64, Some(t.label.clone())))
+            .collect(),
+    };
+    let worst = |a: &(f64, Option<String>), b: &(f64, Option<String>)| a.0.total_cmp(&b.0);
+    match rule.op {
+        AlertOp::Above => candidates.into_iter().max_by(worst),
+        AlertOp::Below => candidates.into_iter().min_by(worst),
+    }
+}
+
+fn breaches(rule: &AlertRule, value: f64) -> bool {
+    match rule.op {
+        AlertOp::Above => value > rule.threshold,
+        AlertOp::Below => value < rule.threshold,
+    }
+}
+
+/// Check every rule against a new sample, firing and resolving alerts.
+pub(super) async fn check(sample: &Sample) {
+    let Some(config) = ALERTS.get() else {
+        return;
+    };
+    if config.rules.is_empty() {
+        return;
+    }
+    let readings = readings(&config.rules).await;
+    let now = sample.ts;
+
+    let mut events = Vec::new();
+    {
+        let mut states = STATES.lock().unwrap();
+        for rule in &config.rules {
+            // a disk or sensor that is missing leaves the rule as it was
+            let Some((value, target)) = value_of(rule, sample, &readings) else {
+                continue;
+            };
+            let state = states.entry(rule.name.clone()).or_default();
+            if breaches(rule, value) {
+                let since = *state.breaching_since.get_or_insert(now);
+                if let Some(alert) = &mut state.firing {
+                    alert.value = value;
+                    alert.target = target;
+                } else if now - since >= rule.for_secs as i64 {
+                    let message = format!(
+                        "{}: {} ({} {})",
+                        rule.name,
+                        describe(rule, target.as_deref(), value),
+                        op_symbol(rule.op),
+                        rule.threshold
+                    );
+                    state.firing = Some(ActiveAlert {
+                        rule: rule.name.clone(),
+                        metric: rule.metric,
+                        target,
+                        value,
+                        op: rule.op,
+                        threshold: rule.threshold,
+                        since,
+                        message: message.clone(),
+                    });
+                    events.push(AlertEvent {
+                        ts: now,
+                        rule: rule.name.clone(),
+                        state: "firing".to_string(),
+                        value,
+                        message,
+                    });
+                }
+            } else {
+                state.breaching_since = None;
+                if state.firing.take().is_some() {
+                    events.push(AlertEvent {
+                        ts: now,
+                        rule: rule.name.clone(),
+                        state: "resolved".to_string(),
+                        value,
+                        message: format!(
+                            "{} resolved: {}",
+                            rule.name,
+                            describe(rule, target.as_deref(), value)
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    for event in events {
+        if event.state == "firing" {
+            tracing::warn!("Alert {}", event.message);
+        } else {
+            tracing::info!("Alert {}", event.message);
+        }
+        if let Some(db) = ALERT_DB.get()
+            && let Err(e) = store(db, &event).await
+        {
+            tracing::error!("Failed to store alert event: {}", e);
+        }
+        for notifier in &config.notifiers {
+            tokio::spawn(notify(notifier.clone(), event.clone()));
+        }
+    }
+}
+
+async fn store(db: &SqlitePool, event: &AlertEvent) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO alert_events (ts, rule, state, value, message) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(event.ts)
+    .bind(&event.rule)
+    .bind(&event.state)
+    .bind(event.value)
+    .bind(&event.message)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+async fn notify(notifier: AlertNotifier, event: AlertEvent) {
+    let result = match &notifier {
+        AlertNotifier::Webhook { url } => send_webhook(url, &event).await,
+        AlertNotifier::Command { command } => run_command(command, &event).await,
+    };
+    if let Err(e) = result {
+        tracing::error!(
+            "Failed to send alert '{}' to {:?}: {}",
+            event.rule,
+            notifier,
+            secrets::redact(&e)
+        );
+    }
+}
+
+async fn send_webhook(url: &str, event: &AlertEvent) -> Result<(), String> {
+    let url = secrets::resolve(url)?;
+    let resp = CLIENT
+        .post(&url)
+        .json(event)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("webhook returned {}", resp.status()))
+    }
+}
+
+async fn run_command(command: &str, event: &AlertEvent) -> Result<(), String> {
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("ALERT_NAME", &event.rule)
+        .env("ALERT_STATE", &event.state)
+        .env("ALERT_VALUE", event.value.to_string())
+        .env("ALERT_MESSAGE", &event.message)
+        .env("ALERT_TS", event.ts.to_string())
+        .kill_on_drop(true)
+        .status();
+    match tokio::time::timeout(NOTIFY_TIMEOUT, child).await {
+        Err(_) => Err("command timed out".to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Ok(Ok(status)) if !status.success() => Err(format!("command exited with {}", status)),
+        Ok(Ok(_)) => Ok(()),
+    }
+}
+
+/// Alerts firing now, for the dashboard and /metrics.
+pub fn active_alerts() -> Vec<ActiveAlert> {
+    let mut active: Vec<ActiveAlert> = STATES
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|state| state.firing.clone())
+        .collect();
+    active.sort_by(|a, b| a.rule.cmp(&b.rule));
+    active
+}
+
+#[derive(Deserialize)]
+pub struct AlertsQuery {
+    /// limit- most history events returned, newest first.
+    limit: Option<u32>,
+}
+
+/// Firing alerts and recent history.
+pub async fn alerts_handler(Query(query): Query<AlertsQuery>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY).min(MAX_HISTORY);
+    let history: Vec<AlertEvent> = match ALERT_DB.get() {
+        Some(db) => sqlx::query_as(
+            "SELECT ts, rule, state, value, message FROM alert_events ORDER BY id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(db)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to read alert history: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let rules = ALERTS.get().map(|config| config.rules.len()).unwrap_or(0);
+    Json(json!({
+        "rules": rules,
+        "active": active_alerts(),
+        "history": history,
+    }))
+}
@@ -0,0 +1,260 @@
+/*
+
+Usage history for procmon.  A background task samples CPU, RAM and swap
+into a ring buffer (and optionally thisbackend.db), served by
+/procmon/history and charted on /protected/procmon.
+
+*/
+use axum::{
+    Json,
+    extract::Query,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+use crate::auth::users::AuthSession;
+use crate::config::{ProcmonConfig, parse_range};
+use crate::htmlv::{get_tera, page_context};
+
+/// Most points returned when no `step` is given.
+const DEFAULT_POINTS: u64 = 720;
+
+/// Most points returned at all.
+const MAX_POINTS: u64 = 5000;
+
+/// Delete expired rows from the database every this many samples.
+const PRUNE_EVERY: u64 = 100;
+
+static HISTORY: Mutex<VecDeque<Sample>> = Mutex::new(VecDeque::new());
+static CONFIG: OnceLock<ProcmonConfig> = OnceLock::new();
+
+/// One point of history.  Usage is in percent; cpu is the average of all cores.
+#[derive(Debug, Clone, Copy, Serialize, sqlx::FromRow)]
+pub struct Sample {
+    /// unix seconds
+    pub ts: i64,
+    pub cpu: f32,
+    pub ram: f32,
+    pub swap: f32,
+}
+
+/// The procmon settings given at startup.
+pub(super) fn config() -> ProcmonConfig {
+    CONFIG.get().cloned().unwrap_or_default()
+}
+
+fn capacity(config: &ProcmonConfig) -> usize {
+    (config.history_hours * 3600 / config.sample_interval_secs.max(1)) as usize
+}
+
+fn push_sample(sample: Sample, capacity: usize) {
+    let mut history = HISTORY.lock().unwrap();
+    while history.len() >= capacity.max(1) {
+        history.pop_front();
+    }
+    history.push_back(sample);
+}
+
+fn take_sample() -> Sample {
+    let usage = super::get_system_usage();
+    let cpu = if usage.cpu_usage.is_empty() {
+        0.0
+    } else {
+        usage.cpu_usage.iter().sum::<f32>() / usage.cpu_usage.len() as f32
+    };
+    Sample {
+        ts: OffsetDateTime::now_utc().unix_timestamp(),
+        cpu,
+        ram: usage.ram_usage,
+        swap: usage.swap_usage,
+    }
+}
+
+async fn load_persisted(db: &SqlitePool, since: i64) -> Result<Vec<Sample>, sqlx::Error> {
+    sqlx::query_as("SELECT ts, cpu, ram, swap FROM procmon_samples WHERE ts >= ? ORDER BY ts")
+        .bind(since)
+        .fetch_all(db)
+        .await
+}
+
+async fn persist(db: &SqlitePool, sample: &Sample) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT OR REPLACE INTO procmon_samples (ts, cpu, ram, swap) VALUES (?, ?, ?, ?)")
+        .bind(sample.ts)
+        .bind(sample.cpu)
+        .bind(sample.ram)
+        .bind(sample.swap)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn prune(db: &SqlitePool, before: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM procmon_samples WHERE ts < ?")
+        .bind(before)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Start the background sampler.  Should be called once at startup.
+pub fn start_history_sampler(config: ProcmonConfig, db: SqlitePool) {
+    if CONFIG.set(config.clone()).is_err() {
+        tracing::warn!("procmon history sampler was already started");
+        return;
+    }
+    let capacity = capacity(&config);
+    let keep_secs = (config.history_hours * 3600) as i64;
+    tracing::info!(
+        "procmon history: every {}s, keeping {}h ({} samples){}",
+        config.sample_interval_secs,
+        config.history_hours,
+        capacity,
+        if config.persist { ", persisted" } else { "" }
+    );
+
+    tokio::spawn(async move {
+        if config.persist {
+            let since = OffsetDateTime::now_utc().unix_timestamp() - keep_secs;
+            match load_persisted(&db, since).await {
+                Ok(samples) => {
+                    tracing::info!("Loaded {} procmon sample(s)", samples.len());
+                    for sample in samples {
+                        push_sample(sample, capacity);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to load procmon history: {}", e),
+            }
+        }
+
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(config.sample_interval_secs.max(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut count: u64 = 0;
+        loop {
+            ticker.tick().await;
+            let sample = take_sample();
+            push_sample(sample, capacity);
+            super::alerts::check(&sample).await;
+
+            if config.persist {
+                if let Err(e) = persist(&db, &sample).await {
+                    tracing::error!("Failed to save procmon sample: {}", e);
+                }
+                if count.is_multiple_of(PRUNE_EVERY)
+                    && let Err(e) = prune(&db, sample.ts - keep_secs).await
+                {
+                    tracing::error!("Failed to prune procmon history: {}", e);
+                }
+            }
+            count += 1;
+        }
+    });
+}
+
+/// Average the samples into buckets `step` seconds wide.
+fn downsample(samples: &[Sample], step: i64) -> Vec<Sample> {
+    let mut points: Vec<Sample> = Vec::new();
+    let mut count = 0.0;
+    for sample in samples {
+        let bucket = sample.ts - sample.ts.rem_euclid(step);
+        match points.last_mut() {
+            Some(point) if point.ts == bucket => {
+                count += 1.0;
+                point.cpu += (sample.cpu - point.cpu) / count;
+                point.ram += (sample.ram - point.ram) / count;
+                point.swap += (sample.swap - point.swap) / count;
+            }
+            _ => {
+                count = 1.0;
+                points.push(Sample {
+                    ts: bucket,
+                    ..*sample
+                });
+            }
+        }
+    }
+    points
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// range- how far back, e.g. "15m", "1h" or "1d".  1h by default.
+    range: Option<String>,
+    /// step- seconds per point.  By default, enough for about 720 points.
+    step: Option<u64>,
+}
+
+fn error_json(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({ "status": "error", "message": message.into() })),
+    )
+        .into_response()
+}
+
+/// History over `range`, averaged into points `step` seconds apart.
+pub async fn history_handler(Query(query): Query<HistoryQuery>) -> Response {
+    let range_text = query.range.as_deref().unwrap_or("1h");
+    // Anything past i64::MAX seconds can't be subtracted from a timestamp.
+    let Some(range) = parse_range(range_text).filter(|r| *r > 0 && i64::try_from(*r).is_ok())
+    else {
+        return error_json(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid range '{}', use e.g. 15m, 1h or 1d", range_text),
+        );
+    };
+    let interval = config().sample_interval_secs.max(1);
+    let step = query
+        .step
+        .unwrap_or_else(|| range.div_ceil(DEFAULT_POINTS))
+        .max(interval)
+        .max(range.div_ceil(MAX_POINTS))
+        .min(range);
+
+    let since = OffsetDateTime::now_utc()
+        .unix_timestamp()
+        .saturating_sub(range as i64);
+    let samples: Vec<Sample> = {
+        let history = HISTORY.lock().unwrap();
+        let start = history.partition_point(|s| s.ts < since);
+        history.range(start..).copied().collect()
+    };
+    let points = if step > interval {
+        downsample(&samples, step as i64)
+    } else {
+        samples
+    };
+
+    Json(json!({
+        "range_secs": range,
+        "interval_secs": interval,
+        "step_secs": step,
+        "samples": points,
+    }))
+    .into_response()
+}
+
+/// The history chart page.
+pub async fn history_page(auth_session: AuthSession) -> impl IntoResponse {
+    let Some(user) = auth_session.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let mut context = page_context();
+    context.insert("title", "System History");
+    context.insert("username", &user.username);
+    match get_tera().render("procmon.html", &context) {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            tracing::error!("Template rendering failed for procmon.html: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
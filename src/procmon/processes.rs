@@ -0,0 +1,244 @@
+/*
+
+Process list for procmon, at /protected/procmon/processes.  Users with a
+high enough access_level may also send SIGTERM or SIGKILL; each attempt
+is written to the audit log.
+
+*/
+use axum::{
+    Json,
+    extract::Query,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System, UpdateKind, Users};
+use tokio::sync::Mutex;
+
+use crate::audit;
+use crate::auth::users::AuthSession;
+use crate::htmlv::{get_tera, page_context};
+
+/// Processes listed when no `limit` is given.
+const DEFAULT_LIMIT: usize = 200;
+
+struct ProcessState {
+    sys: System,
+    users: Users,
+}
+
+/// Kept between requests, since per-process CPU usage is measured
+/// between two refreshes.
+static PROCESSES: Lazy<Mutex<ProcessState>> = Lazy::new(|| {
+    Mutex::new(ProcessState {
+        sys: System::new(),
+        users: Users::new_with_refreshed_list(),
+    })
+});
+
+#[derive(Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub user: String,
+    /// percent of one core
+    pub cpu: f32,
+    pub memory_bytes: u64,
+    pub command: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProcessQuery {
+    /// sort- pid, name, user, cpu or memory.  cpu by default.
+    sort: Option<String>,
+    /// order- asc or desc.  desc by default, asc for name and user.
+    order: Option<String>,
+    /// filter- only processes whose name, user or command contain this.
+    filter: Option<String>,
+    /// limit- most processes returned.
+    limit: Option<usize>,
+}
+
+fn error_json(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({ "status": "error", "message": message.into() })),
+    )
+        .into_response()
+}
+
+async fn list_processes() -> Vec<ProcessInfo> {
+    let mut state = PROCESSES.lock().await;
+    let ProcessState { sys, users } = &mut *state;
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .with_user(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet),
+    );
+    users.refresh();
+    sys.processes()
+        .iter()
+        .map(|(pid, process)| ProcessInfo {
+            pid: pid.as_u32(),
+            name: process.name().to_string_lossy().into_owned(),
+            user: process
+                .user_id()
+                .and_then(|uid| users.get_user_by_id(uid))
+                .map(|user| user.name().to_string())
+                .unwrap_or_default(),
+            cpu: process.cpu_usage(),
+            memory_bytes: process.memory(),
+            command: process
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+        })
+        .collect()
+}
+
+fn sort_processes(processes: &mut [ProcessInfo], sort: &str, descending: bool) -> bool {
+    match sort {
+        "pid" => processes.sort_by_key(|p| p.pid),
+        "name" => processes.sort_by(|a, b| a.name.cmp(&b.name)),
+        "user" => processes.sort_by(|a, b| a.user.cmp(&b.user)),
+        "cpu" => processes.sort_by(|a, b| a.cpu.total_cmp(&b.cpu)),
+        "memory" => processes.sort_by_key(|p| p.memory_bytes),
+        _ => return false,
+    }
+    if descending {
+        processes.reverse();
+    }
+    true
+}
+
+/// Processes as JSON, sorted and filtered.
+pub async fn processes_handler(Query(query): Query<ProcessQuery>) -> Response {
+    let sort = query.sort.as_deref().unwrap_or("cpu");
+    let descending = match query.order.as_deref() {
+        Some("asc") => false,
+        Some("desc") => true,
+        None => !matches!(sort, "name" | "user"),
+        Some(other) => {
+            return error_json(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid order '{}', use asc or desc", other),
+            );
+        }
+    };
+
+    let mut processes = list_processes().await;
+    let total = processes.len();
+    if let Some(filter) = query.filter.as_deref().filter(|f| !f.is_empty()) {
+        let filter = filter.to_lowercase();
+        processes.retain(|p| {
+            p.name.to_lowercase().contains(&filter)
+                || p.user.to_lowercase().contains(&filter)
+                || p.command.to_lowercase().contains(&filter)
+        });
+    }
+    if !sort_processes(&mut processes, sort, descending) {
+        return error_json(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid sort '{}', use pid, name, user, cpu or memory",
+                sort
+            ),
+        );
+    }
+    let matching = processes.len();
+    processes.truncate(query.limit.unwrap_or(DEFAULT_LIMIT));
+
+    Json(json!({
+        "total": total,
+        "matching": matching,
+        "processes": processes,
+    }))
+    .into_response()
+}
+
+fn can_signal(access_level: i32) -> bool {
+    access_level > super::history::config().signal_access_level
+}
+
+/// The process list page.
+pub async fn processes_page(auth_session: AuthSession) -> impl IntoResponse {
+    let Some(user) = auth_session.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let mut context = page_context();
+    context.insert("title", "Processes");
+    context.insert("username", &user.username);
+    context.insert("can_signal", &can_signal(user.access_level));
+    match get_tera().render("processes.html", &context) {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            tracing::error!("Template rendering failed for processes.html: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SignalRequest {
+    pid: u32,
+    /// signal- "TERM" or "KILL".
+    signal: String,
+}
+
+/// Send SIGTERM or SIGKILL to a process, e.g. `{"pid": 1234, "signal": "TERM"}`
+pub async fn signal_handler(
+    auth_session: AuthSession,
+    Json(payload): Json<SignalRequest>,
+) -> Response {
+    let Some(user) = auth_session.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let (signal, signal_name) = match payload.signal.to_ascii_uppercase().as_str() {
+        "TERM" | "SIGTERM" => (Signal::Term, "SIGTERM"),
+        "KILL" | "SIGKILL" => (Signal::Kill, "SIGKILL"),
+        _ => return error_json(StatusCode::BAD_REQUEST, "signal must be TERM or KILL"),
+    };
+    let pid = payload.pid;
+    let mut detail = format!("{} to {}", signal_name, pid);
+
+    let (status, outcome) = if !can_signal(user.access_level) {
+        (StatusCode::FORBIDDEN, "denied: access level too low")
+    } else if pid <= 1 || pid == std::process::id() {
+        (StatusCode::FORBIDDEN, "denied: protected process")
+    } else {
+        let mut state = PROCESSES.lock().await;
+        let pid = Pid::from_u32(pid);
+        state.sys.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+        match state.sys.process(pid) {
+            None => (StatusCode::NOT_FOUND, "no such process"),
+            Some(process) => {
+                detail = format!("{} ({})", detail, process.name().to_string_lossy());
+                match process.kill_with(signal) {
+                    Some(true) => (StatusCode::OK, "ok"),
+                    Some(false) => (StatusCode::INTERNAL_SERVER_ERROR, "failed"),
+                    None => (StatusCode::BAD_REQUEST, "signal not supported"),
+                }
+            }
+        }
+    };
+
+    audit::record(&user.username, "signal", &detail, outcome).await;
+    if status == StatusCode::OK {
+        Json(json!({ "status": "ok", "message": format!("Sent {}", detail) })).into_response()
+    } else {
+        error_json(status, format!("{}: {}", detail, outcome))
+    }
+}
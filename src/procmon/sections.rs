@@ -0,0 +1,219 @@
+/*
+
+Optional sections of the /procmon JSON: disks, networks, temperatures,
+load, uptime and processes.  Each is only gathered when asked for with
+`?include=`, so the status bar's frequent polling stays cheap.
+
+*/
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use sysinfo::{Components, Disks, Networks, ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::sync::Mutex;
+
+/// Sections that can be included, in the order listed in errors.
+const SECTION_NAMES: [&str; 6] = [
+    "disks",
+    "networks",
+    "temperatures",
+    "load",
+    "uptime",
+    "processes",
+];
+
+#[derive(Deserialize)]
+pub struct ProcmonQuery {
+    /// include- comma separated sections, or "all".
+    include: Option<String>,
+}
+
+/// Which optional sections to gather.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sections {
+    pub disks: bool,
+    pub networks: bool,
+    pub temperatures: bool,
+    pub load: bool,
+    pub uptime: bool,
+    pub processes: bool,
+}
+
+impl Sections {
+    pub fn from_query(query: &ProcmonQuery) -> Result<Self, String> {
+        let mut sections = Sections::default();
+        let Some(include) = &query.include else {
+            return Ok(sections);
+        };
+        for name in include.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "all" => {
+                    sections = Sections {
+                        disks: true,
+                        networks: true,
+                        temperatures: true,
+                        load: true,
+                        uptime: true,
+                        processes: true,
+                    }
+                }
+                "disks" => sections.disks = true,
+                "networks" => sections.networks = true,
+                "temperatures" => sections.temperatures = true,
+                "load" => sections.load = true,
+                "uptime" => sections.uptime = true,
+                "processes" => sections.processes = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown section '{}', use any of: all, {}",
+                        name,
+                        SECTION_NAMES.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(sections)
+    }
+}
+
+#[derive(Serialize)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    pub name: String,
+    pub file_system: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    /// percent used
+    pub usage: f32,
+}
+
+#[derive(Serialize)]
+pub struct NetworkUsage {
+    pub interface: String,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    pub total_rx_bytes: u64,
+    pub total_tx_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct Temperature {
+    pub label: String,
+    /// degrees Celsius
+    pub celsius: f32,
+    pub critical: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+/// Networks, and when they were last refreshed, to turn byte counts into rates.
+struct NetworkState {
+    networks: Networks,
+    refreshed_at: Instant,
+}
+
+static DISKS: Lazy<Mutex<Disks>> = Lazy::new(|| Mutex::new(Disks::new_with_refreshed_list()));
+static NETWORKS: Lazy<Mutex<NetworkState>> = Lazy::new(|| {
+    Mutex::new(NetworkState {
+        networks: Networks::new_with_refreshed_list(),
+        refreshed_at: Instant::now(),
+    })
+});
+static COMPONENTS: Lazy<Mutex<Components>> =
+    Lazy::new(|| Mutex::new(Components::new_with_refreshed_list()));
+static PROCESSES: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
+
+pub async fn disks() -> Vec<DiskUsage> {
+    let mut disks = DISKS.lock().await;
+    disks.refresh(true);
+    disks
+        .list()
+        .iter()
+        .map(|disk| {
+            let total = disk.total_space();
+            let available = disk.available_space();
+            DiskUsage {
+                mount_point: disk.mount_point().display().to_string(),
+                name: disk.name().to_string_lossy().into_owned(),
+                file_system: disk.file_system().to_string_lossy().into_owned(),
+                total_bytes: total,
+                available_bytes: available,
+                usage: if total > 0 {
+                    total.saturating_sub(available) as f32 / total as f32 * 100.0
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect()
+}
+
+/// Rates are averaged over the time since the previous call.
+pub async fn networks() -> Vec<NetworkUsage> {
+    let mut state = NETWORKS.lock().await;
+    let elapsed = state.refreshed_at.elapsed().as_secs_f64();
+    state.networks.refresh(true);
+    state.refreshed_at = Instant::now();
+
+    let rate = |bytes: u64| {
+        if elapsed > 0.0 {
+            bytes as f64 / elapsed
+        } else {
+            0.0
+        }
+    };
+    let mut networks: Vec<NetworkUsage> = state
+        .networks
+        .list()
+        .iter()
+        .map(|(interface, data)| NetworkUsage {
+            interface: interface.clone(),
+            rx_bytes_per_sec: rate(data.received()),
+            tx_bytes_per_sec: rate(data.transmitted()),
+            total_rx_bytes: data.total_received(),
+            total_tx_bytes: data.total_transmitted(),
+        })
+        .collect();
+    networks.sort_by(|a, b| a.interface.cmp(&b.interface));
+    networks
+}
+
+/// Sensors that currently report a temperature.
+pub async fn temperatures() -> Vec<Temperature> {
+    let mut components = COMPONENTS.lock().await;
+    components.refresh(true);
+    components
+        .list()
+        .iter()
+        .filter_map(|component| {
+            Some(Temperature {
+                label: component.label().to_string(),
+                celsius: component.temperature()?,
+                critical: component.critical(),
+            })
+        })
+        .collect()
+}
+
+pub fn load() -> LoadAverage {
+    let load = System::load_average();
+    LoadAverage {
+        one: load.one,
+        five: load.five,
+        fifteen: load.fifteen,
+    }
+}
+
+pub fn uptime() -> u64 {
+    System::uptime()
+}
+
+pub async fn process_count() -> usize {
+    let mut sys = PROCESSES.lock().await;
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+    sys.processes().len()
+}
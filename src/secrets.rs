@@ -0,0 +1,218 @@
+/*
+
+Secrets for route configs, such as API keys.  Stored in the `secrets`
+table of thisbackend.db, or given as SECRET_<NAME> environment variables
+(which take precedence).  Route configs refer to them as ${secret:NAME}.
+
+*/
+use regex::{Captures, Regex};
+use sqlx::SqlitePool;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::{LazyLock, OnceLock};
+use time::OffsetDateTime;
+
+/// Prefix of environment variables holding secrets.
+const ENV_PREFIX: &str = "SECRET_";
+
+/// Values shorter than this are not redacted, so short secrets do not
+/// mangle unrelated log text.
+const MIN_REDACT_LEN: usize = 4;
+
+/// Stands in for an unset secret in `resolve_or_placeholder`.  Left as is
+/// by urlencoding and JSON, so `redact` still finds it.
+const PLACEHOLDER: &str = "__UNSET_SECRET__";
+
+static SECRET_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{secret:([A-Za-z0-9_]+)\}").expect("valid secret regex"));
+
+static SECRETS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Secrets given as SECRET_<NAME> environment variables.
+fn env_secrets() -> HashMap<String, String> {
+    std::env::vars()
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix(ENV_PREFIX)?;
+            valid_name(name).then(|| (name.to_string(), value))
+        })
+        .collect()
+}
+
+/// Load all secrets.  Should be called once at startup, after migrations.
+pub async fn load_secrets(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT name, value FROM secrets")
+        .fetch_all(db)
+        .await?;
+    let mut secrets: HashMap<String, String> = rows.into_iter().collect();
+    secrets.extend(env_secrets());
+
+    tracing::info!("Loaded {} secret(s).", secrets.len());
+    if SECRETS.set(secrets).is_err() {
+        tracing::warn!("Secrets were already loaded");
+    }
+    Ok(())
+}
+
+/// Replace every ${secret:NAME} in `text` with the secret's value.
+/// Fails naming the first secret that is not set.
+pub fn resolve(text: &str) -> Result<String, String> {
+    let mut missing = None;
+    let resolved = resolve_with(text, |name| {
+        missing.get_or_insert_with(|| name.to_string());
+        String::new()
+    });
+    match missing {
+        Some(name) => Err(format!("Secret '{}' is not set", name)),
+        None => Ok(resolved),
+    }
+}
+
+/// Like `resolve`, but a secret that is not set becomes a placeholder
+/// which `redact` hides the same way as a real value.  For requests that
+/// never reach the upstream, such as replayed fixtures.
+pub fn resolve_or_placeholder(text: &str) -> Result<String, String> {
+    Ok(resolve_with(text, |_| PLACEHOLDER.to_string()))
+}
+
+fn resolve_with(text: &str, mut missing: impl FnMut(&str) -> String) -> String {
+    if !text.contains("${secret:") {
+        return text.to_string();
+    }
+    let secrets = SECRETS.get();
+    SECRET_REF
+        .replace_all(text, |caps: &Captures| {
+            match secrets.and_then(|s| s.get(&caps[1])) {
+                Some(value) => value.clone(),
+                None => missing(&caps[1]),
+            }
+        })
+        .into_owned()
+}
+
+/// Hide any secret values (plain or urlencoded) in `text`, for logging.
+pub fn redact(text: &str) -> String {
+    let mut redacted = text.replace(PLACEHOLDER, "[REDACTED]");
+    let Some(secrets) = SECRETS.get() else {
+        return redacted;
+    };
+    for value in secrets.values().filter(|v| v.len() >= MIN_REDACT_LEN) {
+        let encoded = serde_urlencoded::to_string([("", value)]).unwrap_or_default();
+        let encoded = encoded.trim_start_matches('=');
+        for form in [value.as_str(), encoded] {
+            if !form.is_empty() && redacted.contains(form) {
+                redacted = redacted.replace(form, "[REDACTED]");
+            }
+        }
+    }
+    redacted
+}
+
+/// Restrict the database to its owner, since it holds secrets.  Call
+/// after migrations whenever the database is opened.
+#[cfg(unix)]
+pub fn restrict_db_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    if let Err(e) =
+        std::fs::set_permissions("./thisbackend.db", std::fs::Permissions::from_mode(0o600))
+    {
+        eprintln!(
+            "Warning: could not restrict permissions on thisbackend.db: {}",
+            e
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restrict_db_permissions() {}
+
+fn prompt_secret_value(name: &str) -> io::Result<String> {
+    print!("Enter value for secret '{}': ", name);
+    io::stdout().flush()?;
+    let mut value = String::new();
+    io::stdin().read_line(&mut value)?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn usage() -> Box<dyn Error> {
+    "Usage: secret set NAME [VALUE] | secret list | secret delete NAME".into()
+}
+
+/// The `secret` command line: set, list or delete stored secrets.
+///
+/// `secret set NAME` without a value prompts for it, keeping it out of
+/// the shell history.
+pub async fn secret_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = SqliteConnectOptions::new()
+        .filename("./thisbackend.db")
+        .create_if_missing(true);
+    let db = SqlitePool::connect_with(options).await?;
+    sqlx::migrate!().run(&db).await?;
+    restrict_db_permissions();
+
+    match args.first().map(String::as_str) {
+        Some("set") => {
+            let name = args.get(1).ok_or_else(usage)?;
+            if !valid_name(name) {
+                return Err("Secret names may only use letters, digits and '_'".into());
+            }
+            let value = match args.get(2) {
+                Some(value) => value.clone(),
+                None => prompt_secret_value(name)?,
+            };
+            sqlx::query(
+                r#"
+                INSERT INTO secrets (name, value, updated_at) VALUES (?, ?, ?)
+                ON CONFLICT(name) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(name)
+            .bind(value)
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&db)
+            .await?;
+            println!("Secret '{}' saved.  Restart the server to use it.", name);
+        }
+        Some("list") => {
+            let rows: Vec<(String, i64)> =
+                sqlx::query_as("SELECT name, updated_at FROM secrets ORDER BY name")
+                    .fetch_all(&db)
+                    .await?;
+            let env = env_secrets();
+            for (name, updated_at) in &rows {
+                let updated = OffsetDateTime::from_unix_timestamp(*updated_at)
+                    .map(|t| t.date().to_string())
+                    .unwrap_or_default();
+                let note = if env.contains_key(name) {
+                    " (overridden by environment)"
+                } else {
+                    ""
+                };
+                println!("{}\tdatabase\t{}{}", name, updated, note);
+            }
+            let mut env_names: Vec<&String> = env.keys().collect();
+            env_names.sort();
+            for name in env_names {
+                println!("{}\tenvironment", name);
+            }
+        }
+        Some("delete") => {
+            let name = args.get(1).ok_or_else(usage)?;
+            let result = sqlx::query("DELETE FROM secrets WHERE name = ?")
+                .bind(name)
+                .execute(&db)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Err(format!("No stored secret named '{}'", name).into());
+            }
+            println!("Secret '{}' deleted.", name);
+        }
+        _ => return Err(usage()),
+    }
+    Ok(())
+}
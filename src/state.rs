@@ -1,4 +1,4 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::HashMap;
 
 use serde_json::json;
 use std::sync::{Mutex, OnceLock};
@@ -30,7 +30,7 @@ impl AppSingleton {
     }
 
     pub fn init() {
-        APP_SINGLETON.get_or_init(|| AppSingleton::new());
+        APP_SINGLETON.get_or_init(AppSingleton::new);
     }
 
     pub fn instance() -> &'static AppSingleton {
@@ -47,12 +47,14 @@ impl AppSingleton {
     }
 
     /// Get all keys from the hashstatus map
+    #[allow(dead_code)]
     pub fn get_all_keys(&self) -> Vec<String> {
         let obj = self.obj.lock().unwrap();
         obj.hashstatus.keys().cloned().collect()
     }
 
     /// Retrieve a status entry from the hashstatus map
+    #[allow(dead_code)]
     pub fn get_status(&self, key: &str) -> Option<String> {
         let obj = self.obj.lock().unwrap();
         let result = obj.hashstatus.get(key).cloned();
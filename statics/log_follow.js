@@ -0,0 +1,68 @@
+// log_follow.js
+// Live view for get_logs pages: streams new lines from ?follow=true
+// into #log-lines.  Does nothing on pages without the follow controls.
+(function () {
+    const controls = document.querySelector('.log-follow[data-follow-url]');
+    const list = document.getElementById('log-lines');
+    if (!controls || !list) return;
+
+    const followBox = document.getElementById('log-follow');
+    const pauseBtn = document.getElementById('log-pause');
+    const status = document.getElementById('log-follow-status');
+    const maxLines = 2000;
+    let source = null;
+    let paused = false;
+    let held = [];
+
+    function appendLine(line) {
+        const li = document.createElement('li');
+        li.textContent = line;
+        list.appendChild(li);
+        while (list.children.length > maxLines) list.removeChild(list.firstChild);
+        li.scrollIntoView({ block: 'nearest' });
+    }
+
+    function setStatus() {
+        if (!source) status.textContent = '';
+        else if (paused) status.textContent = `Paused (${held.length} new)`;
+        else status.textContent = 'Following';
+    }
+
+    function start() {
+        source = new EventSource(controls.dataset.followUrl);
+        source.onmessage = (event) => {
+            if (paused) held.push(event.data);
+            else appendLine(event.data);
+            setStatus();
+        };
+        source.onerror = () => {
+            status.textContent = 'Reconnecting...';
+        };
+        pauseBtn.disabled = false;
+    }
+
+    function stop() {
+        if (source) source.close();
+        source = null;
+        paused = false;
+        held = [];
+        pauseBtn.textContent = 'Pause';
+        pauseBtn.disabled = true;
+    }
+
+    followBox.onchange = () => {
+        if (followBox.checked) start();
+        else stop();
+        setStatus();
+    };
+
+    pauseBtn.onclick = () => {
+        paused = !paused;
+        pauseBtn.textContent = paused ? 'Resume' : 'Pause';
+        if (!paused) {
+            held.forEach(appendLine);
+            held = [];
+        }
+        setStatus();
+    };
+})();
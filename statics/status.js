@@ -1,4 +1,29 @@
 // status.js
+// Fill the status bar from a /procmon snapshot.
+function renderStatusBar(data) {
+    const cpuUsages = data.cpu_usage;
+    const mid = Math.ceil(cpuUsages.length / 2);
+
+    const firstHalf = cpuUsages
+        .slice(0, mid)
+        .map(usage => `${usage.toFixed(2)}%`)
+        .join(' | ');
+
+    const secondHalf = cpuUsages
+        .slice(mid)
+        .map(usage => `${usage.toFixed(2)}%`)
+        .join(' | ');
+
+    // Update the status bar fields
+    document.querySelector('.status-bar .status-bar-field:nth-child(1)').textContent = `CPU 0: ${cpuUsages[0].toFixed(2)}%`;
+    document.querySelector('.status-bar .status-bar-field:nth-child(2)').textContent =  `CPU 1: ${cpuUsages[1].toFixed(2)}%`;
+    document.querySelector('.status-bar .status-bar-field:nth-child(3)').textContent =  `CPU 2: ${cpuUsages[2].toFixed(2)}%`;
+    document.querySelector('.status-bar .status-bar-field:nth-child(4)').textContent =  `CPU 3: ${cpuUsages[3].toFixed(2)}%`;
+    document.querySelector('.status-bar .status-bar-field:nth-child(5)').textContent = `RAM Usage: ${data.ram_usage.toFixed(2)}%`;
+    //document.querySelector('.status-bar .status-bar-field:nth-child(5)').textContent = `Swap Usage: ${data.swap_usage.toFixed(2)}%`;
+}
+
+// Fetch /procmon once and update the status bar.
 async function updateStatusBar() {
     try {
         // Fetch data from the /procmon endpoint
@@ -8,29 +33,27 @@ async function updateStatusBar() {
             throw new Error('Failed to fetch data');
         }
 
-        const data = await response.json();
-
-        const cpuUsages = data.cpu_usage;
-        const mid = Math.ceil(cpuUsages.length / 2);
-
-        const firstHalf = cpuUsages
-            .slice(0, mid)
-            .map(usage => `${usage.toFixed(2)}%`)
-            .join(' | ');
-
-        const secondHalf = cpuUsages
-            .slice(mid)
-            .map(usage => `${usage.toFixed(2)}%`)
-            .join(' | ');
-
-        // Update the status bar fields
-        document.querySelector('.status-bar-field:nth-child(1)').textContent = `CPU 0: ${cpuUsages[0].toFixed(2)}%`;
-        document.querySelector('.status-bar-field:nth-child(2)').textContent =  `CPU 1: ${cpuUsages[1].toFixed(2)}%`;
-        document.querySelector('.status-bar-field:nth-child(3)').textContent =  `CPU 2: ${cpuUsages[2].toFixed(2)}%`;
-        document.querySelector('.status-bar-field:nth-child(4)').textContent =  `CPU 3: ${cpuUsages[3].toFixed(2)}%`;
-        document.querySelector('.status-bar-field:nth-child(5)').textContent = `RAM Usage: ${data.ram_usage.toFixed(2)}%`;
-        //document.querySelector('.status-bar-field:nth-child(5)').textContent = `Swap Usage: ${data.swap_usage.toFixed(2)}%`;
+        renderStatusBar(await response.json());
     } catch (error) {
         console.error('Error updating status bar:', error);
     }
 }
+
+// Keep the status bar up to date from the server's live usage stream.
+// Falls back to polling where EventSource isn't available.
+function startStatusBar() {
+    if (!window.EventSource) {
+        updateStatusBar();
+        setInterval(updateStatusBar, 1000);
+        return;
+    }
+    // the browser reconnects by itself if the stream drops
+    const source = new EventSource('/procmon/stream');
+    source.onmessage = (event) => {
+        try {
+            renderStatusBar(JSON.parse(event.data));
+        } catch (error) {
+            console.error('Error updating status bar:', error);
+        }
+    };
+}